@@ -25,6 +25,19 @@ fn input_types(func: &syn::ItemFn) -> Vec<syn::Type> {
         .collect()
 }
 
+/// Generic lifetime parameter `'arg` if any input type is a reference modified by `input_types`
+fn arg_lifetime(func: &syn::ItemFn) -> TokenStream {
+    let has_reference = func.sig.inputs.iter().any(|arg| match arg {
+        syn::FnArg::Typed(val) => matches!(*val.ty, syn::Type::Reference(_)),
+        _ => false,
+    });
+    if has_reference {
+        quote! { 'arg, }
+    } else {
+        quote! {}
+    }
+}
+
 fn accel_path() -> String {
     if let Ok(name) = proc_macro_crate::crate_name("accel") {
         // accel exists as an external crate
@@ -55,6 +68,7 @@ fn accel_path() -> String {
 
 fn impl_submodule(ptx_str: &str, func: &syn::ItemFn) -> TokenStream {
     let input_types = input_types(func);
+    let arg_lifetime = arg_lifetime(func);
     let accel = accel_path();
 
     let launchable: syn::Path = syn::parse_str(&format!(
@@ -65,10 +79,13 @@ fn impl_submodule(ptx_str: &str, func: &syn::ItemFn) -> TokenStream {
     .unwrap();
 
     let targets: Vec<syn::Ident> = (1..=input_types.len())
-        .into_iter()
         .map(|k| syn::Ident::new(&format!("Target{}", k), Span::call_site()))
         .collect();
 
+    let args_types: Vec<syn::Ident> = (1..=input_types.len())
+        .map(|k| syn::Ident::new(&format!("Arg{}", k), Span::call_site()))
+        .collect();
+
     let ident = &func.sig.ident;
 
     let accel = syn::Ident::new(&accel, Span::call_site());
@@ -86,6 +103,29 @@ fn impl_submodule(ptx_str: &str, func: &syn::ItemFn) -> TokenStream {
                 }
             }
 
+            /// Launch with dynamic shared memory and a stream specified in `config`
+            ///
+            /// Load `Module` once by `Module::new`, and reuse it for every launch.
+            /// This blocks until the kernel completes only if `config.stream` is `None`.
+            ///
+            /// Safety
+            /// ------
+            /// - If `config.stream` is given, memories in `args` must not be freed,
+            ///   or accessed from host, until the kernel completes
+            pub unsafe fn launch_with<#arg_lifetime #(#args_types),* >(
+                module: &Module,
+                config: #accel::LaunchConfig<'_>,
+                args: (#(#args_types,)*)
+            ) -> #accel::error::Result<()>
+            where
+                #(
+                    #args_types: #accel::execution::DeviceSend<Target = #input_types>
+                ),*
+            {
+                use #launchable;
+                module.launch_with(config, args)
+            }
+
             impl<'arg> #launchable <'arg> for Module {
                 #(
                     type #targets = #input_types;
@@ -107,7 +147,6 @@ fn caller(func: &syn::ItemFn) -> TokenStream {
     let input_types = input_types(func);
 
     let args_types: Vec<syn::Ident> = (1..=input_types.len())
-        .into_iter()
         .map(|k| syn::Ident::new(&format!("Arg{}", k), Span::call_site()))
         .collect();
 
@@ -161,6 +200,10 @@ mod tests {
     fn kernel_name(arg1: i32, arg2: f64) {}
     "#;
 
+    const TEST_KERNEL_REF: &'static str = r#"
+    fn kernel_name(arg1: &i32, arg2: f64) {}
+    "#;
+
     /// Format TokenStream by rustfmt
     ///
     /// This can test if the input TokenStream is valid in terms of rustfmt.
@@ -186,6 +229,17 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn arg_lifetime() -> Result<()> {
+        let func: syn::ItemFn = syn::parse_str(TEST_KERNEL)?;
+        assert!(super::arg_lifetime(&func).is_empty());
+        let func: syn::ItemFn = syn::parse_str(TEST_KERNEL_REF)?;
+        assert_eq!(super::arg_lifetime(&func).to_string(), "'arg ,");
+        let ts = super::impl_submodule("", &func);
+        pretty_print(&ts)?;
+        Ok(())
+    }
+
     #[test]
     fn caller() -> Result<()> {
         let func: syn::ItemFn = syn::parse_str(TEST_KERNEL)?;
@@ -33,73 +33,116 @@ pub fn generate(item: TokenStream) -> TokenStream {
                         &self,
                         grid: impl Into<Grid>,
                         block: impl Into<Block>,
-                        (#(#args_value,)*): (#(#args_types,)*),
+                        args: (#(#args_types,)*),
                     ) -> Result<()>
                     where
                         #(
                             #args_types: DeviceSend<Target = Self::#targets>
                         ),*
                     {
-                        let grid = grid.into();
-                        let block = block.into();
-                        let kernel = self.get_kernel()?;
-                        let mut args = [#(#args_value.as_kernel_parameter()),*];
-                        unsafe {
-                            contexted_call!(
-                                &kernel,
-                                cuLaunchKernel,
-                                kernel.func,
-                                grid.x,
-                                grid.y,
-                                grid.z,
-                                block.x,
-                                block.y,
-                                block.z,
-                                0,          /* FIXME: no shared memory */
-                                null_mut(), /* use default stream */
-                                args.as_mut_ptr(),
-                                null_mut() /* no extra */
-                            )?;
-                        }
-                        kernel.sync()?;
-                        Ok(())
+                        // Safe since this blocks until the kernel completes
+                        unsafe { self.launch_with(LaunchConfig::new(grid, block), args) }
                     }
 
                     fn launch_async<#(#args_types),*>(
                         &self,
                         grid: impl Into<Grid>,
                         block: impl Into<Block>,
-                        (#(#args_value,)*): (#(#args_types,)*),
+                        args: (#(#args_types,)*),
                     ) -> ::futures::future::BoxFuture<'arg, Result<()>>
                     where
                         #(
                             #args_types: DeviceSend<Target = Self::#targets> + 'arg
                         ),*
                     {
-                        let grid = grid.into();
-                        let block = block.into();
-                        let kernel = self.get_kernel().unwrap();
-                        let stream = stream::Stream::new(kernel.get_ref());
+                        let launch = || -> Result<stream::PooledStream> {
+                            let kernel = self.get_kernel()?;
+                            let stream = stream::StreamPool::for_context(kernel.get_ref()).checkout()?;
+                            // Safe since the returned future borrows arguments until the kernel completes
+                            unsafe { self.launch_with(LaunchConfig::new(grid, block).on(&stream), args)? };
+                            Ok(stream)
+                        };
+                        match launch() {
+                            Ok(stream) => Box::pin(stream.into_future()),
+                            // reported when awaited as other errors in async launch
+                            Err(e) => Box::pin(::futures::future::ready(Err(e))),
+                        }
+                    }
+
+                    /// Enqueue the kernel into `stream` without synchronization
+                    ///
+                    /// Use `Stream::sync` or `Event` to wait for the completion.
+                    ///
+                    /// Safety
+                    /// ------
+                    /// - Memories in `args` must not be freed, or accessed from host, until the kernel completes
+                    unsafe fn launch_on<#(#args_types),*>(
+                        &self,
+                        stream: &Stream,
+                        grid: impl Into<Grid>,
+                        block: impl Into<Block>,
+                        args: (#(#args_types,)*),
+                    ) -> Result<()>
+                    where
+                        #(
+                            #args_types: DeviceSend<Target = Self::#targets>
+                        ),*
+                    {
+                        self.launch_with(LaunchConfig::new(grid, block).on(stream), args)
+                    }
+
+                    /// Launch with dynamic shared memory and a stream specified in `config`
+                    ///
+                    /// This blocks until the kernel completes only if `config.stream` is `None`.
+                    /// `config` is checked by `LaunchConfig::validate_for` only if the driver rejects the launch,
+                    /// so that the reason is reported as `AccelError::InvalidLaunchConfig`.
+                    ///
+                    /// Safety
+                    /// ------
+                    /// - If `config.stream` is given, memories in `args` must not be freed,
+                    ///   or accessed from host, until the kernel completes
+                    unsafe fn launch_with<#(#args_types),*>(
+                        &self,
+                        config: LaunchConfig<'_>,
+                        (#(#args_value,)*): (#(#args_types,)*),
+                    ) -> Result<()>
+                    where
+                        #(
+                            #args_types: DeviceSend<Target = Self::#targets>
+                        ),*
+                    {
+                        let kernel = self.get_kernel()?;
+                        let LaunchConfig {
+                            grid,
+                            block,
+                            shared_mem_bytes,
+                            stream,
+                        } = config;
                         let mut args = [#(#args_value.as_kernel_parameter()),*];
-                        unsafe {
-                            contexted_call!(
-                                &kernel,
-                                cuLaunchKernel,
-                                kernel.func,
-                                grid.x,
-                                grid.y,
-                                grid.z,
-                                block.x,
-                                block.y,
-                                block.z,
-                                0, /* FIXME: no shared memory */
-                                stream.stream,
-                                args.as_mut_ptr(),
-                                null_mut() /* no extra */
-                            )
+                        let launched = contexted_call!(
+                            &kernel,
+                            cuLaunchKernel,
+                            kernel.func,
+                            grid.x,
+                            grid.y,
+                            grid.z,
+                            block.x,
+                            block.y,
+                            block.z,
+                            shared_mem_bytes,
+                            stream.map_or(null_mut(), |stream| stream.stream),
+                            args.as_mut_ptr(),
+                            null_mut() /* no extra */
+                        );
+                        if let Err(e) = launched {
+                            // Queries for the diagnosis are kept out of successful launches
+                            config.validate_for(&kernel)?;
+                            return Err(e);
+                        }
+                        if stream.is_none() {
+                            kernel.sync()?;
                         }
-                        .expect("Asynchronous kernel launch has been failed");
-                        Box::pin(stream.into_future())
+                        Ok(())
                     }
                 }
             }
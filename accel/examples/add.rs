@@ -26,14 +26,15 @@ fn main() -> error::Result<()> {
         b[i] = 2.0 * i as f32;
     }
 
+    // Block size maximizing occupancy, and enough blocks to cover `n`
+    let module = add::Module::new(&ctx)?;
+    let block = module.get_kernel()?.suggest_block_size(0)?.block_size as usize;
+    let grid = (n + block - 1) / block;
+
     // Launch kernel synchronously
-    add(
-        &ctx,
-        1, /* grid */
-        n, /* block */
-        (a.as_ptr(), b.as_ptr(), c.as_mut_ptr(), n),
-    )
-    .expect("Kernel call failed");
+    module
+        .launch(grid, block, (a.as_ptr(), b.as_ptr(), c.as_mut_ptr(), n))
+        .expect("Kernel call failed");
 
     Ok(())
 }
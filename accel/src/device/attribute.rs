@@ -0,0 +1,316 @@
+//! Typed [Device attributes]
+//!
+//! [Device attributes]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__DEVICE.html#group__CUDA__DEVICE_1g9c3e1414f0ad901d3278a4d6645fc266
+
+use super::*;
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use cuda::CUdevice_attribute_enum as Attr;
+
+/// Compute capability of GPU, e.g. `sm_75` is `7.5`
+///
+/// Ordered by major then minor version:
+///
+/// ```
+/// # use accel::*;
+/// assert!(ComputeCapability::new(7, 5) > ComputeCapability::new(7, 0));
+/// assert!(ComputeCapability::new(8, 0) > ComputeCapability::new(7, 5));
+/// assert_eq!(ComputeCapability::new(7, 5).to_string(), "7.5");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComputeCapability {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ComputeCapability {
+    pub fn new(major: u32, minor: u32) -> Self {
+        ComputeCapability { major, minor }
+    }
+}
+
+impl fmt::Display for ComputeCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Typed snapshot of attributes of a device
+///
+/// Memory sizes are in bytes, and clock rates are in kHz as the Driver API reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceAttributes {
+    pub compute_capability: ComputeCapability,
+    /// Number of streaming multiprocessors (SM)
+    pub multiprocessor_count: u32,
+    pub warp_size: u32,
+    pub max_threads_per_block: u32,
+    pub max_threads_per_multiprocessor: u32,
+    /// Maximum size of each dimension of a block
+    pub max_block_dim: Block,
+    /// Maximum size of each dimension of a grid
+    pub max_grid_dim: Grid,
+    /// Maximum static shared memory available to a thread block
+    pub max_shared_memory_per_block: usize,
+    /// Maximum shared memory a thread block can opt-in with dynamic shared memory
+    pub max_shared_memory_per_block_optin: usize,
+    pub max_shared_memory_per_multiprocessor: usize,
+    /// Maximum number of 32-bit registers available to a thread block
+    pub max_registers_per_block: u32,
+    pub max_registers_per_multiprocessor: u32,
+    pub total_constant_memory: usize,
+    pub l2_cache_size: usize,
+    /// Peak clock frequency in kHz
+    pub clock_rate: u32,
+    /// Peak memory clock frequency in kHz
+    pub memory_clock_rate: u32,
+    /// Global memory bus width in bits
+    pub global_memory_bus_width: u32,
+    /// Number of asynchronous engines, i.e. memcpy which can overlap with kernel execution
+    pub async_engine_count: u32,
+    pub concurrent_kernels: bool,
+    pub ecc_enabled: bool,
+    /// Device is integrated with the host memory system
+    pub integrated: bool,
+    pub can_map_host_memory: bool,
+    pub unified_addressing: bool,
+    pub managed_memory: bool,
+    pub stream_priorities_supported: bool,
+}
+
+/// Convert a raw attribute value of a count or size, which must not be negative
+pub(crate) fn to_count<A: fmt::Debug>(attr: A, value: i32) -> Result<u32> {
+    value
+        .to_u32()
+        .ok_or_else(|| AccelError::InvalidAttributeValue {
+            attribute: format!("{:?}", attr),
+            value,
+        })
+}
+
+impl DeviceAttributes {
+    /// Decode attributes from raw values of `cuDeviceGetAttribute`
+    ///
+    /// A negative value for a count or size is reported as `AccelError::InvalidAttributeValue`.
+    pub(crate) fn decode<F>(mut get: F) -> Result<Self>
+    where
+        F: FnMut(CUdevice_attribute) -> Result<i32>,
+    {
+        let mut count = |attr: Attr| -> Result<u32> { to_count(attr, get(attr)?) };
+        Ok(DeviceAttributes {
+            compute_capability: ComputeCapability {
+                major: count(Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?,
+                minor: count(Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?,
+            },
+            multiprocessor_count: count(Attr::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT)?,
+            warp_size: count(Attr::CU_DEVICE_ATTRIBUTE_WARP_SIZE)?,
+            max_threads_per_block: count(Attr::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK)?,
+            max_threads_per_multiprocessor: count(
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR,
+            )?,
+            max_block_dim: Block::xyz(
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X)?,
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y)?,
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z)?,
+            ),
+            max_grid_dim: Grid::xyz(
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X)?,
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y)?,
+                count(Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z)?,
+            ),
+            max_shared_memory_per_block: count(
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK,
+            )? as usize,
+            max_shared_memory_per_block_optin: count(
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN,
+            )? as usize,
+            max_shared_memory_per_multiprocessor: count(
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR,
+            )? as usize,
+            max_registers_per_block: count(Attr::CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK)?,
+            max_registers_per_multiprocessor: count(
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_MULTIPROCESSOR,
+            )?,
+            total_constant_memory: count(Attr::CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY)? as usize,
+            l2_cache_size: count(Attr::CU_DEVICE_ATTRIBUTE_L2_CACHE_SIZE)? as usize,
+            clock_rate: count(Attr::CU_DEVICE_ATTRIBUTE_CLOCK_RATE)?,
+            memory_clock_rate: count(Attr::CU_DEVICE_ATTRIBUTE_MEMORY_CLOCK_RATE)?,
+            global_memory_bus_width: count(Attr::CU_DEVICE_ATTRIBUTE_GLOBAL_MEMORY_BUS_WIDTH)?,
+            async_engine_count: count(Attr::CU_DEVICE_ATTRIBUTE_ASYNC_ENGINE_COUNT)?,
+            concurrent_kernels: count(Attr::CU_DEVICE_ATTRIBUTE_CONCURRENT_KERNELS)? != 0,
+            ecc_enabled: count(Attr::CU_DEVICE_ATTRIBUTE_ECC_ENABLED)? != 0,
+            integrated: count(Attr::CU_DEVICE_ATTRIBUTE_INTEGRATED)? != 0,
+            can_map_host_memory: count(Attr::CU_DEVICE_ATTRIBUTE_CAN_MAP_HOST_MEMORY)? != 0,
+            unified_addressing: count(Attr::CU_DEVICE_ATTRIBUTE_UNIFIED_ADDRESSING)? != 0,
+            managed_memory: count(Attr::CU_DEVICE_ATTRIBUTE_MANAGED_MEMORY)? != 0,
+            stream_priorities_supported: count(
+                Attr::CU_DEVICE_ATTRIBUTE_STREAM_PRIORITIES_SUPPORTED,
+            )? != 0,
+        })
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Attributes of each device, which never change while the process is running
+    static ref CACHED_ATTRIBUTES: Mutex<HashMap<CUdevice, DeviceAttributes>> = Mutex::new(HashMap::new());
+}
+
+impl Device {
+    /// Get a raw attribute value by `cuDeviceGetAttribute`
+    pub fn get_attribute(&self, attr: CUdevice_attribute) -> Result<i32> {
+        let value = unsafe { ffi_new!(cuDeviceGetAttribute, attr, self.device)? };
+        Ok(value)
+    }
+
+    /// Get compute capability of this device
+    pub fn compute_capability(&self) -> Result<ComputeCapability> {
+        let count = |attr: Attr| to_count(attr, self.get_attribute(attr)?);
+        Ok(ComputeCapability {
+            major: count(Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?,
+            minor: count(Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?,
+        })
+    }
+
+    /// Get typed attributes of this device
+    ///
+    /// ```
+    /// # use accel::*;
+    /// let device = Device::nth(0).unwrap();
+    /// let attr = device.attributes().unwrap();
+    /// assert_eq!(attr.warp_size, 32);
+    /// assert!(attr.max_block_dim.x >= 1024);
+    /// ```
+    pub fn attributes(&self) -> Result<DeviceAttributes> {
+        DeviceAttributes::decode(|attr| self.get_attribute(attr))
+    }
+
+    /// Get typed attributes of this device, which are queried only once in the process
+    ///
+    /// This is used to validate kernel launches without querying the driver every time.
+    pub fn cached_attributes(&self) -> Result<DeviceAttributes> {
+        if let Some(attr) = CACHED_ATTRIBUTES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&self.device)
+        {
+            return Ok(attr.clone());
+        }
+        // The lock is not held while querying the driver
+        let attr = self.attributes()?;
+        CACHED_ATTRIBUTES
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(self.device, attr.clone());
+        Ok(attr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::turing;
+
+    fn decode(table: &HashMap<Attr, i32>) -> Result<DeviceAttributes> {
+        DeviceAttributes::decode(|attr| Ok(table[&attr]))
+    }
+
+    #[test]
+    fn decode_turing() -> Result<()> {
+        let attr = decode(&turing())?;
+        assert_eq!(attr.compute_capability, ComputeCapability::new(7, 5));
+        assert_eq!(attr.multiprocessor_count, 40);
+        assert_eq!(attr.warp_size, 32);
+        assert_eq!(attr.max_block_dim, Block::xyz(1024, 1024, 64));
+        assert_eq!(attr.max_grid_dim, Grid::xyz(2147483647, 65535, 65535));
+        assert_eq!(attr.max_shared_memory_per_block, 48 * 1024);
+        assert_eq!(attr.l2_cache_size, 4 * 1024 * 1024);
+        assert_eq!(attr.clock_rate, 1590000);
+        assert!(attr.concurrent_kernels);
+        assert!(!attr.ecc_enabled);
+        assert!(attr.stream_priorities_supported);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_flags() -> Result<()> {
+        // (raw value, decoded flag)
+        for &(raw, expected) in &[(0, false), (1, true), (2, true)] {
+            let mut table = turing();
+            table.insert(Attr::CU_DEVICE_ATTRIBUTE_ECC_ENABLED, raw);
+            table.insert(Attr::CU_DEVICE_ATTRIBUTE_INTEGRATED, raw);
+            let attr = decode(&table)?;
+            assert_eq!(attr.ecc_enabled, expected);
+            assert_eq!(attr.integrated, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decode_error() {
+        let table = turing();
+        let result = DeviceAttributes::decode(|attr| {
+            if attr == Attr::CU_DEVICE_ATTRIBUTE_WARP_SIZE {
+                Err(AccelError::CUDAError {
+                    api_name: "cuDeviceGetAttribute".into(),
+                    error: cudaError_enum::CUDA_ERROR_INVALID_VALUE,
+                })
+            } else {
+                Ok(table[&attr])
+            }
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn decode_negative() {
+        let mut table = turing();
+        table.insert(Attr::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT, -1);
+        match decode(&table) {
+            Err(AccelError::InvalidAttributeValue { attribute, value }) => {
+                assert_eq!(attribute, "CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT");
+                assert_eq!(value, -1);
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn compute_capability_order() {
+        // (lhs, rhs, lhs < rhs)
+        let table = [
+            ((3, 5), (5, 0), true),
+            ((5, 2), (6, 0), true),
+            ((7, 0), (7, 5), true),
+            ((7, 5), (7, 5), false),
+            ((8, 6), (8, 0), false),
+            ((10, 0), (9, 9), false),
+        ];
+        for &((a, b), (c, d), less) in &table {
+            let lhs = ComputeCapability::new(a, b);
+            let rhs = ComputeCapability::new(c, d);
+            assert_eq!(lhs < rhs, less, "{} < {}", lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn attributes() -> Result<()> {
+        let device = Device::nth(0)?;
+        let attr = device.attributes()?;
+        assert_eq!(attr.compute_capability, device.compute_capability()?);
+        assert_eq!(attr.warp_size, 32);
+        assert!(attr.multiprocessor_count > 0);
+        assert!(attr.max_threads_per_block >= attr.warp_size);
+        assert!(attr.max_threads_per_multiprocessor >= attr.max_threads_per_block);
+        assert!(attr.max_block_dim.x <= attr.max_threads_per_block);
+        assert!(attr.max_block_dim.z <= attr.max_block_dim.x);
+        assert!(attr.max_grid_dim.x >= 65535);
+        assert!(attr.max_shared_memory_per_block > 0);
+        assert!(attr.max_shared_memory_per_multiprocessor >= attr.max_shared_memory_per_block);
+        assert!(attr.max_registers_per_block > 0);
+        assert!(attr.total_constant_memory > 0);
+        assert!(attr.clock_rate > 0);
+        assert_eq!(device.cached_attributes()?, attr);
+        assert_eq!(device.cached_attributes()?, attr);
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Builder of CUDA context with [flags](./struct.ContextFlags.html)
+
+use super::*;
+
+/// How the host thread waits results from the GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduling {
+    /// Heuristic based on the number of active contexts and processors (default)
+    Auto,
+    /// Actively spin, which decreases latency but may lower the performance of CPU threads
+    Spin,
+    /// Yield the thread, which increases latency but improves the performance of CPU threads
+    Yield,
+    /// Block the thread on a synchronization primitive
+    BlockingSync,
+}
+
+impl Scheduling {
+    fn flags(self) -> ContextFlags {
+        match self {
+            Scheduling::Auto => ContextFlags::empty(),
+            Scheduling::Spin => ContextFlags::SCHED_SPIN,
+            Scheduling::Yield => ContextFlags::SCHED_YIELD,
+            Scheduling::BlockingSync => ContextFlags::SCHED_BLOCKING_SYNC,
+        }
+    }
+}
+
+const SCHED_MASK: ContextFlags = ContextFlags::from_bits_truncate(
+    ContextFlags::SCHED_SPIN.bits()
+        | ContextFlags::SCHED_YIELD.bits()
+        | ContextFlags::SCHED_BLOCKING_SYNC.bits(),
+);
+
+/// Check the combination of flags is valid on the device
+///
+/// - Only one scheduling mode can be specified
+/// - `MAP_HOST` requires the device can map host memory
+fn validate(flags: ContextFlags, can_map_host_memory: bool) -> Result<()> {
+    if (flags & SCHED_MASK).bits().count_ones() > 1 {
+        return Err(AccelError::InvalidContextFlags {
+            flags,
+            reason: "Multiple scheduling modes are specified",
+        });
+    }
+    if flags.contains(ContextFlags::MAP_HOST) && !can_map_host_memory {
+        return Err(AccelError::InvalidContextFlags {
+            flags,
+            reason: "Device cannot map host memory",
+        });
+    }
+    Ok(())
+}
+
+/// Builder of a new CUDA context
+///
+/// ```
+/// # use accel::*;
+/// let device = Device::nth(0).unwrap();
+/// let ctx = device
+///     .context_builder()
+///     .scheduling(Scheduling::BlockingSync)
+///     .map_host(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ContextBuilder<'device> {
+    device: &'device Device,
+    flags: ContextFlags,
+}
+
+impl<'device> ContextBuilder<'device> {
+    /// Set scheduling mode, overwrites previous one
+    pub fn scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.flags.remove(SCHED_MASK);
+        self.flags.insert(scheduling.flags());
+        self
+    }
+
+    /// Support mapped pinned allocations
+    pub fn map_host(mut self, enable: bool) -> Self {
+        self.flags.set(ContextFlags::MAP_HOST, enable);
+        self
+    }
+
+    /// Keep local memory allocation after launching a kernel which requires large local memory
+    pub fn lmem_resize_to_max(mut self, enable: bool) -> Self {
+        self.flags.set(ContextFlags::LMEM_RESIZE_TO_MAX, enable);
+        self
+    }
+
+    /// Overwrite all flags
+    ///
+    /// The combination is validated in `build`.
+    pub fn flags(mut self, flags: ContextFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Create a new context
+    pub fn build(self) -> Result<Context> {
+        let can_map_host_memory = self
+            .device
+            .get_attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_CAN_MAP_HOST_MEMORY)?
+            != 0;
+        validate(self.flags, can_map_host_memory)?;
+        let ptr = unsafe { ffi_new!(cuCtxCreate_v2, self.flags.bits(), self.device.device)? };
+        if ptr.is_null() {
+            return Err(AccelError::ContextCreationFailed {
+                reason: "cuCtxCreate returns a null context".into(),
+            });
+        }
+        // The new context is pushed onto the context stack of this thread
+        let popped = unsafe { ffi_new!(cuCtxPopCurrent_v2) };
+        if popped.as_ref().ok() != Some(&ptr) {
+            // Destroy the new context since it is never returned
+            if let Err(e) = unsafe { ffi_call!(cuCtxDestroy_v2, ptr) } {
+                log::error!("Context remove failed: {:?}", e);
+            }
+            let popped = popped?;
+            return Err(AccelError::ContextCreationFailed {
+                reason: format!(
+                    "{:?} is popped from the context stack instead of the new context {:?}",
+                    popped, ptr
+                ),
+            });
+        }
+        registry::register(ptr);
+        Ok(Arc::new(ContextOwned { ptr, primary: None }))
+    }
+}
+
+impl Device {
+    /// Start building a new context with flags
+    pub fn context_builder(&self) -> ContextBuilder<'_> {
+        ContextBuilder {
+            device: self,
+            flags: ContextFlags::empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_flags() {
+        // (flags, can_map_host_memory, valid)
+        let table = [
+            (ContextFlags::empty(), false, true),
+            (ContextFlags::SCHED_SPIN, false, true),
+            (ContextFlags::SCHED_BLOCKING_SYNC, false, true),
+            (
+                ContextFlags::SCHED_SPIN | ContextFlags::SCHED_YIELD,
+                true,
+                false,
+            ),
+            (
+                ContextFlags::SCHED_YIELD | ContextFlags::SCHED_BLOCKING_SYNC,
+                true,
+                false,
+            ),
+            (ContextFlags::MAP_HOST, true, true),
+            (ContextFlags::MAP_HOST, false, false),
+            (
+                ContextFlags::SCHED_SPIN | ContextFlags::LMEM_RESIZE_TO_MAX,
+                false,
+                true,
+            ),
+        ];
+        for &(flags, can_map_host_memory, valid) in &table {
+            assert_eq!(
+                validate(flags, can_map_host_memory).is_ok(),
+                valid,
+                "{:?}",
+                flags
+            );
+        }
+    }
+
+    #[test]
+    fn scheduling_overwrite() {
+        let device = Device { device: 0 };
+        let builder = device
+            .context_builder()
+            .scheduling(Scheduling::Spin)
+            .map_host(true)
+            .scheduling(Scheduling::BlockingSync);
+        assert_eq!(
+            builder.flags,
+            ContextFlags::SCHED_BLOCKING_SYNC | ContextFlags::MAP_HOST
+        );
+        let builder = builder.scheduling(Scheduling::Auto).map_host(false);
+        assert_eq!(builder.flags, ContextFlags::empty());
+    }
+
+    #[test]
+    fn build() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device
+            .context_builder()
+            .scheduling(Scheduling::BlockingSync)
+            .lmem_resize_to_max(true)
+            .build()?;
+        ctx.sync()?;
+        Ok(())
+    }
+
+    #[test]
+    fn build_invalid() -> Result<()> {
+        let device = Device::nth(0)?;
+        let result = device
+            .context_builder()
+            .flags(ContextFlags::SCHED_SPIN | ContextFlags::SCHED_YIELD)
+            .build();
+        assert!(result.is_err());
+        Ok(())
+    }
+}
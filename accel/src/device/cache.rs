@@ -0,0 +1,263 @@
+//! L1 cache and shared memory configuration of CUDA context
+//!
+//! Kernel-level configurations are set by `Kernel::set_cache_config` and `Kernel::set_shared_mem_config`,
+//! which overwrite the context-level ones.
+
+use super::*;
+
+/// Preference of L1 cache and shared memory split on devices where they share the same on-chip memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheConfig {
+    /// No preference (default)
+    PreferNone,
+    /// Prefer larger shared memory and smaller L1 cache
+    PreferShared,
+    /// Prefer larger L1 cache and smaller shared memory
+    PreferL1,
+    /// Prefer equal sized L1 cache and shared memory
+    PreferEqual,
+}
+
+impl CacheConfig {
+    pub(crate) fn raw(self) -> CUfunc_cache {
+        match self {
+            CacheConfig::PreferNone => CUfunc_cache::CU_FUNC_CACHE_PREFER_NONE,
+            CacheConfig::PreferShared => CUfunc_cache::CU_FUNC_CACHE_PREFER_SHARED,
+            CacheConfig::PreferL1 => CUfunc_cache::CU_FUNC_CACHE_PREFER_L1,
+            CacheConfig::PreferEqual => CUfunc_cache::CU_FUNC_CACHE_PREFER_EQUAL,
+        }
+    }
+
+    fn from_raw(raw: CUfunc_cache) -> Self {
+        match raw {
+            CUfunc_cache::CU_FUNC_CACHE_PREFER_NONE => CacheConfig::PreferNone,
+            CUfunc_cache::CU_FUNC_CACHE_PREFER_SHARED => CacheConfig::PreferShared,
+            CUfunc_cache::CU_FUNC_CACHE_PREFER_L1 => CacheConfig::PreferL1,
+            CUfunc_cache::CU_FUNC_CACHE_PREFER_EQUAL => CacheConfig::PreferEqual,
+        }
+    }
+}
+
+/// Bank size of shared memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedMemConfig {
+    /// Default bank size of the device
+    DefaultBankSize,
+    /// 4-byte bank size
+    FourByteBankSize,
+    /// 8-byte bank size, which reduces bank conflicts for 64-bit values, e.g. `f64`
+    EightByteBankSize,
+}
+
+impl SharedMemConfig {
+    pub(crate) fn raw(self) -> CUsharedconfig {
+        match self {
+            SharedMemConfig::DefaultBankSize => {
+                CUsharedconfig::CU_SHARED_MEM_CONFIG_DEFAULT_BANK_SIZE
+            }
+            SharedMemConfig::FourByteBankSize => {
+                CUsharedconfig::CU_SHARED_MEM_CONFIG_FOUR_BYTE_BANK_SIZE
+            }
+            SharedMemConfig::EightByteBankSize => {
+                CUsharedconfig::CU_SHARED_MEM_CONFIG_EIGHT_BYTE_BANK_SIZE
+            }
+        }
+    }
+
+    fn from_raw(raw: CUsharedconfig) -> Self {
+        match raw {
+            CUsharedconfig::CU_SHARED_MEM_CONFIG_DEFAULT_BANK_SIZE => {
+                SharedMemConfig::DefaultBankSize
+            }
+            CUsharedconfig::CU_SHARED_MEM_CONFIG_FOUR_BYTE_BANK_SIZE => {
+                SharedMemConfig::FourByteBankSize
+            }
+            CUsharedconfig::CU_SHARED_MEM_CONFIG_EIGHT_BYTE_BANK_SIZE => {
+                SharedMemConfig::EightByteBankSize
+            }
+        }
+    }
+}
+
+/// Preferred split of the unified L1 cache / shared memory, set by `Kernel::set_preferred_carveout`
+///
+/// This is only a hint, and the driver may choose a different split if required to run the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedMemoryCarveout {
+    /// No preference (default)
+    Default,
+    /// Prefer maximum L1 cache, i.e. 0% of the unified memory as shared memory
+    MaxL1,
+    /// Prefer maximum shared memory, i.e. 100% of the unified memory as shared memory
+    MaxShared,
+    /// Prefer the given percentage (0 to 100) of the unified memory as shared memory
+    ///
+    /// `AccelError::InvalidCarveout` is returned for a percentage larger than 100.
+    Percent(u8),
+}
+
+impl SharedMemoryCarveout {
+    pub(crate) fn raw(self) -> Result<i32> {
+        Ok(match self {
+            SharedMemoryCarveout::Default => {
+                CUshared_carveout::CU_SHAREDMEM_CARVEOUT_DEFAULT as i32
+            }
+            SharedMemoryCarveout::MaxL1 => CUshared_carveout::CU_SHAREDMEM_CARVEOUT_MAX_L1 as i32,
+            SharedMemoryCarveout::MaxShared => {
+                CUshared_carveout::CU_SHAREDMEM_CARVEOUT_MAX_SHARED as i32
+            }
+            SharedMemoryCarveout::Percent(percent) if percent <= 100 => percent as i32,
+            SharedMemoryCarveout::Percent(percent) => {
+                return Err(AccelError::InvalidCarveout { percent })
+            }
+        })
+    }
+
+    /// `None` if `raw` is neither the default nor a percentage
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        if raw == CUshared_carveout::CU_SHAREDMEM_CARVEOUT_DEFAULT as i32 {
+            Some(SharedMemoryCarveout::Default)
+        } else if raw == CUshared_carveout::CU_SHAREDMEM_CARVEOUT_MAX_L1 as i32 {
+            Some(SharedMemoryCarveout::MaxL1)
+        } else if raw == CUshared_carveout::CU_SHAREDMEM_CARVEOUT_MAX_SHARED as i32 {
+            Some(SharedMemoryCarveout::MaxShared)
+        } else if 0 < raw && raw < 100 {
+            Some(SharedMemoryCarveout::Percent(raw as u8))
+        } else {
+            None
+        }
+    }
+}
+
+fn ctx_set_cache_config(ctx: ContextRef, config: CacheConfig) -> Result<()> {
+    unsafe { contexted_call!(&ctx, cuCtxSetCacheConfig, config.raw()) }
+}
+
+fn ctx_get_cache_config(ctx: ContextRef) -> Result<CacheConfig> {
+    let raw = unsafe { contexted_new!(&ctx, cuCtxGetCacheConfig)? };
+    Ok(CacheConfig::from_raw(raw))
+}
+
+fn ctx_set_shared_mem_config(ctx: ContextRef, config: SharedMemConfig) -> Result<()> {
+    unsafe { contexted_call!(&ctx, cuCtxSetSharedMemConfig, config.raw()) }
+}
+
+fn ctx_get_shared_mem_config(ctx: ContextRef) -> Result<SharedMemConfig> {
+    let raw = unsafe { contexted_new!(&ctx, cuCtxGetSharedMemConfig)? };
+    Ok(SharedMemConfig::from_raw(raw))
+}
+
+macro_rules! impl_cache_config {
+    ($ctx:ty) => {
+        impl $ctx {
+            /// Set preferred cache configuration for all kernels in this context
+            ///
+            /// This is only a preference, and ignored on devices with fixed L1 cache and shared memory sizes.
+            pub fn set_cache_config(&self, config: CacheConfig) -> Result<()> {
+                ctx_set_cache_config(ContextRef { ptr: self.ptr }, config)
+            }
+
+            /// Get preferred cache configuration of this context
+            pub fn get_cache_config(&self) -> Result<CacheConfig> {
+                ctx_get_cache_config(ContextRef { ptr: self.ptr })
+            }
+
+            /// Set shared memory bank size for all kernels in this context
+            pub fn set_shared_mem_config(&self, config: SharedMemConfig) -> Result<()> {
+                ctx_set_shared_mem_config(ContextRef { ptr: self.ptr }, config)
+            }
+
+            /// Get shared memory bank size of this context
+            pub fn get_shared_mem_config(&self) -> Result<SharedMemConfig> {
+                ctx_get_shared_mem_config(ContextRef { ptr: self.ptr })
+            }
+        }
+    };
+}
+
+impl_cache_config!(ContextOwned);
+impl_cache_config!(ContextRef);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_config_raw() {
+        for &config in &[
+            CacheConfig::PreferNone,
+            CacheConfig::PreferShared,
+            CacheConfig::PreferL1,
+            CacheConfig::PreferEqual,
+        ] {
+            assert_eq!(CacheConfig::from_raw(config.raw()), config);
+        }
+    }
+
+    #[test]
+    fn shared_mem_config_raw() {
+        for &config in &[
+            SharedMemConfig::DefaultBankSize,
+            SharedMemConfig::FourByteBankSize,
+            SharedMemConfig::EightByteBankSize,
+        ] {
+            assert_eq!(SharedMemConfig::from_raw(config.raw()), config);
+        }
+    }
+
+    #[test]
+    fn carveout_raw() -> Result<()> {
+        for &carveout in &[
+            SharedMemoryCarveout::Default,
+            SharedMemoryCarveout::MaxL1,
+            SharedMemoryCarveout::MaxShared,
+            SharedMemoryCarveout::Percent(50),
+        ] {
+            assert_eq!(
+                SharedMemoryCarveout::from_raw(carveout.raw()?),
+                Some(carveout)
+            );
+        }
+        // Boundary percentages are reported as the named preferences
+        assert_eq!(
+            SharedMemoryCarveout::from_raw(SharedMemoryCarveout::Percent(0).raw()?),
+            Some(SharedMemoryCarveout::MaxL1)
+        );
+        assert_eq!(
+            SharedMemoryCarveout::from_raw(SharedMemoryCarveout::Percent(100).raw()?),
+            Some(SharedMemoryCarveout::MaxShared)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn carveout_out_of_range() {
+        match SharedMemoryCarveout::Percent(101).raw() {
+            Err(AccelError::InvalidCarveout { percent: 101 }) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        // not truncated into a percentage
+        for &raw in &[-2, 101, 256 + 50] {
+            assert_eq!(SharedMemoryCarveout::from_raw(raw), None);
+        }
+    }
+
+    #[test]
+    fn cache_config() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        ctx.set_cache_config(CacheConfig::PreferShared)?;
+        let _config = ctx.get_cache_config()?; // ignored on some devices
+        Ok(())
+    }
+
+    #[test]
+    fn shared_mem_config() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        ctx.get_ref()
+            .set_shared_mem_config(SharedMemConfig::FourByteBankSize)?;
+        let _config = ctx.get_shared_mem_config()?; // ignored on some devices
+        Ok(())
+    }
+}
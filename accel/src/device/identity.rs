@@ -0,0 +1,265 @@
+//! Stable identifiers of devices
+//!
+//! Ordinals used in `Device::nth` may change between reboots or by `CUDA_VISIBLE_DEVICES`.
+//! UUID and PCI bus id are stable for each physical device.
+
+use super::*;
+use std::{ffi::CString, fmt, str::FromStr};
+
+/// UUID of a device
+///
+/// Formatted in the same manner as `nvidia-smi -L`, and parsed with or without `GPU-` prefix:
+///
+/// ```
+/// # use accel::*;
+/// let uuid: DeviceUuid = "GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c".parse().unwrap();
+/// assert_eq!(uuid.to_string(), "GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c");
+/// let uuid2: DeviceUuid = "1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c".parse().unwrap();
+/// assert_eq!(uuid, uuid2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceUuid(pub [u8; 16]);
+
+impl fmt::Display for DeviceUuid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GPU-")?;
+        for (i, byte) in self.0.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DeviceUuid {
+    type Err = AccelError;
+    fn from_str(value: &str) -> Result<Self> {
+        let invalid = || AccelError::InvalidDeviceId {
+            value: value.into(),
+        };
+        // `str::strip_prefix` is not available on the pinned toolchain
+        let s = match value.get(..4) {
+            Some("GPU-") => &value[4..],
+            _ => value,
+        };
+        let groups: Vec<&str> = s.split('-').collect();
+        let lengths: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+        if lengths != [8, 4, 4, 4, 12] {
+            return Err(invalid());
+        }
+        let hex = groups.concat();
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let mut bytes = [0_u8; 16];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(DeviceUuid(bytes))
+    }
+}
+
+/// PCI bus id of a device, i.e. `domain:bus:device.function` in hex
+///
+/// The domain can be omitted in parsing as `cuDeviceGetByPCIBusId` accepts:
+///
+/// ```
+/// # use accel::*;
+/// let id: PciBusId = "0000:65:00.0".parse().unwrap();
+/// assert_eq!(id, PciBusId { domain: 0, bus: 0x65, device: 0, function: 0 });
+/// assert_eq!(id.to_string(), "0000:65:00.0");
+/// let id2: PciBusId = "65:00.0".parse().unwrap();
+/// assert_eq!(id, id2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PciBusId {
+    pub domain: u32,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl fmt::Display for PciBusId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{:x}",
+            self.domain, self.bus, self.device, self.function
+        )
+    }
+}
+
+impl FromStr for PciBusId {
+    type Err = AccelError;
+    fn from_str(value: &str) -> Result<Self> {
+        let invalid = || AccelError::InvalidDeviceId {
+            value: value.into(),
+        };
+        let hex = |s: &str, max_len: usize| -> Result<u32> {
+            if s.is_empty() || s.len() > max_len || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            u32::from_str_radix(s, 16).map_err(|_| invalid())
+        };
+        let mut fields: Vec<&str> = value.trim().split(':').collect();
+        let (domain, bus) = match fields.len() {
+            2 => (0, hex(fields[0], 2)?),
+            3 => (hex(fields[0], 8)?, hex(fields[1], 2)?),
+            _ => return Err(invalid()),
+        };
+        let last = fields.pop().unwrap();
+        let mut device_function = last.split('.');
+        let device = hex(device_function.next().ok_or_else(invalid)?, 2)?;
+        let function = hex(device_function.next().ok_or_else(invalid)?, 1)?;
+        if device_function.next().is_some() || device > 0x1f || function > 7 {
+            return Err(invalid());
+        }
+        Ok(PciBusId {
+            domain,
+            bus: bus as u8,
+            device: device as u8,
+            function: function as u8,
+        })
+    }
+}
+
+impl Device {
+    /// Get UUID of this device
+    pub fn uuid(&self) -> Result<DeviceUuid> {
+        let uuid = unsafe { ffi_new!(cuDeviceGetUuid, self.device)? };
+        let mut bytes = [0_u8; 16];
+        for (byte, &raw) in bytes.iter_mut().zip(uuid.bytes.iter()) {
+            *byte = raw as u8;
+        }
+        Ok(DeviceUuid(bytes))
+    }
+
+    /// Get PCI bus id of this device
+    pub fn pci_bus_id(&self) -> Result<PciBusId> {
+        // "[domain]:[bus]:[device].[function]" with 13 characters and null terminator
+        let mut bytes = vec![0_u8; 64];
+        unsafe {
+            ffi_call!(
+                cuDeviceGetPCIBusId,
+                bytes.as_mut_ptr() as *mut i8,
+                bytes.len() as i32,
+                self.device
+            )?;
+        }
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).parse()
+    }
+
+    /// Get a device by its PCI bus id, e.g. `"0000:65:00.0"`
+    pub fn by_pci_bus_id(id: &str) -> Result<Self> {
+        Self::init();
+        let id: PciBusId = id.parse()?;
+        let id = CString::new(id.to_string()).unwrap();
+        let device = unsafe { ffi_new!(cuDeviceGetByPCIBusId, id.as_ptr())? };
+        Ok(Device { device })
+    }
+
+    /// Get a device by its UUID
+    pub fn by_uuid(uuid: &DeviceUuid) -> Result<Self> {
+        let devices = Self::all()?;
+        for device in &devices {
+            if device.uuid()? == *uuid {
+                return Ok(*device);
+            }
+        }
+        Err(AccelError::DeviceNotMatched {
+            selector: format!("UUID {}", uuid),
+            count: devices.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_format() {
+        let uuid = DeviceUuid([
+            0x1c, 0x0d, 0x9b, 0x1a, 0x4a, 0x2b, 0x8f, 0x3e, 0x5d, 0x6c, 0x7b, 0x8a, 0x9f, 0x0e,
+            0x1d, 0x2c,
+        ]);
+        assert_eq!(uuid.to_string(), "GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c");
+        assert_eq!(uuid.to_string().parse::<DeviceUuid>().unwrap(), uuid);
+    }
+
+    #[test]
+    fn uuid_parse() {
+        // (input, valid)
+        let table = [
+            ("GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c", true),
+            ("1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c", true),
+            ("GPU-1C0D9B1A-4A2B-8F3E-5D6C-7B8A9F0E1D2C", true),
+            ("GPU-00000000-0000-0000-0000-000000000000", true),
+            ("GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2", false),
+            ("GPU-1c0d9b1a4a2b-8f3e-5d6c-7b8a9f0e1d2c", false),
+            ("GPU-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2x", false),
+            ("GPU-+c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c", false),
+            ("MIG-1c0d9b1a-4a2b-8f3e-5d6c-7b8a9f0e1d2c", false),
+            ("", false),
+        ];
+        for &(input, valid) in &table {
+            assert_eq!(input.parse::<DeviceUuid>().is_ok(), valid, "{}", input);
+        }
+    }
+
+    #[test]
+    fn pci_bus_id_parse() {
+        // (input, expected)
+        let table = [
+            ("0000:65:00.0", Some((0, 0x65, 0, 0))),
+            ("00000000:65:00.0", Some((0, 0x65, 0, 0))),
+            ("0001:af:1f.7", Some((1, 0xaf, 0x1f, 7))),
+            ("0000:AF:00.1", Some((0, 0xaf, 0, 1))),
+            ("65:00.0", Some((0, 0x65, 0, 0))),
+            ("0000:65:00", None),
+            ("0000:65:20.0", None),
+            ("0000:65:00.8", None),
+            ("0000:165:00.0", None),
+            ("0000:65:00.0.0", None),
+            ("000000000:65:00.0", None),
+            ("0:0:0:0.0", None),
+            ("zz:00.0", None),
+            ("+5:00.0", None),
+            ("", None),
+        ];
+        for &(input, expected) in &table {
+            let expected = expected.map(|(domain, bus, device, function)| PciBusId {
+                domain,
+                bus,
+                device,
+                function,
+            });
+            assert_eq!(input.parse::<PciBusId>().ok(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn pci_bus_id_format() {
+        let id = PciBusId {
+            domain: 0x1,
+            bus: 0xaf,
+            device: 0x1f,
+            function: 7,
+        };
+        assert_eq!(id.to_string(), "0001:af:1f.7");
+        assert_eq!(id.to_string().parse::<PciBusId>().unwrap(), id);
+    }
+
+    #[test]
+    fn lookup() -> Result<()> {
+        let device = Device::nth(0)?;
+        let uuid = device.uuid()?;
+        assert_eq!(Device::by_uuid(&uuid)?, device);
+        let id = device.pci_bus_id()?;
+        assert_eq!(Device::by_pci_bus_id(&id.to_string())?, device);
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! Resource limits of CUDA context, see [cuCtxSetLimit]
+//!
+//! [cuCtxSetLimit]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g0651954dfb9788173e60a9af7201e65a
+
+use super::*;
+
+/// Resource limits of a context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Stack size in bytes of each GPU thread
+    StackSize,
+    /// Size in bytes of the FIFO used by `printf` in device code, e.g. `accel_core::println!`
+    PrintfFifoSize,
+    /// Size in bytes of the heap used by `malloc` in device code, e.g. `accel_core::PTXAllocator`
+    MallocHeapSize,
+    /// Maximum nesting depth of a grid at which a thread can safely synchronize with device runtime
+    DevRuntimeSyncDepth,
+    /// Maximum number of outstanding device runtime launches
+    DevRuntimePendingLaunchCount,
+}
+
+impl Limit {
+    fn raw(self) -> CUlimit {
+        match self {
+            Limit::StackSize => CUlimit::CU_LIMIT_STACK_SIZE,
+            Limit::PrintfFifoSize => CUlimit::CU_LIMIT_PRINTF_FIFO_SIZE,
+            Limit::MallocHeapSize => CUlimit::CU_LIMIT_MALLOC_HEAP_SIZE,
+            Limit::DevRuntimeSyncDepth => CUlimit::CU_LIMIT_DEV_RUNTIME_SYNC_DEPTH,
+            Limit::DevRuntimePendingLaunchCount => {
+                CUlimit::CU_LIMIT_DEV_RUNTIME_PENDING_LAUNCH_COUNT
+            }
+        }
+    }
+}
+
+fn ctx_set_limit(ctx: ContextRef, limit: Limit, value: usize) -> Result<()> {
+    unsafe { contexted_call!(&ctx, cuCtxSetLimit, limit.raw(), value) }
+}
+
+fn ctx_get_limit(ctx: ContextRef, limit: Limit) -> Result<usize> {
+    unsafe { contexted_new!(&ctx, cuCtxGetLimit, limit.raw()) }
+}
+
+macro_rules! impl_limit {
+    ($ctx:ty) => {
+        impl $ctx {
+            /// Set a resource limit of this context
+            ///
+            /// The driver may round up the value, use `get_limit` to get the actual value.
+            /// Limits must be set before launching kernels which use the resources.
+            pub fn set_limit(&self, limit: Limit, value: usize) -> Result<()> {
+                ctx_set_limit(ContextRef { ptr: self.ptr }, limit, value)
+            }
+
+            /// Get a resource limit of this context
+            pub fn get_limit(&self, limit: Limit) -> Result<usize> {
+                ctx_get_limit(ContextRef { ptr: self.ptr }, limit)
+            }
+        }
+    };
+}
+
+impl_limit!(ContextOwned);
+impl_limit!(ContextRef);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw() {
+        // (typed limit, raw limit)
+        let table = [
+            (Limit::StackSize, 0),
+            (Limit::PrintfFifoSize, 1),
+            (Limit::MallocHeapSize, 2),
+            (Limit::DevRuntimeSyncDepth, 3),
+            (Limit::DevRuntimePendingLaunchCount, 4),
+        ];
+        for &(limit, raw) in &table {
+            assert_eq!(limit.raw() as u32, raw);
+        }
+    }
+
+    #[test]
+    fn malloc_heap_size() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let size = 64 * 1024 * 1024;
+        ctx.set_limit(Limit::MallocHeapSize, size)?;
+        assert!(ctx.get_limit(Limit::MallocHeapSize)? >= size);
+        Ok(())
+    }
+
+    #[test]
+    fn printf_fifo_size_ref() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let ctx_ref = ctx.get_ref();
+        let size = 4 * 1024 * 1024;
+        ctx_ref.set_limit(Limit::PrintfFifoSize, size)?;
+        assert!(ctx.get_limit(Limit::PrintfFifoSize)? >= size);
+        Ok(())
+    }
+}
@@ -3,16 +3,35 @@
 //! [Device]:  https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__DEVICE.html
 //! [Context]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html
 
+mod attribute;
+mod builder;
+mod cache;
+mod identity;
+mod limit;
+mod peer;
+mod primary;
+pub(crate) mod registry;
+mod selector;
+
+pub use attribute::*;
+pub use builder::*;
+pub use cache::*;
+pub use identity::*;
+pub use limit::*;
+pub use primary::*;
+pub use selector::*;
+
 use crate::{error::*, *};
 use cuda::*;
+use num_traits::ToPrimitive;
 use std::sync::{Arc, Once};
 
 pub use accel_derive::Contexted;
 
 /// Handler for device and its primary context
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Device {
-    device: CUdevice,
+    pub(crate) device: CUdevice,
 }
 
 impl Device {
@@ -63,6 +82,8 @@ impl Device {
                 self.device
             )?;
         }
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        bytes.truncate(len);
         Ok(String::from_utf8(bytes).expect("GPU name is not UTF8"))
     }
 
@@ -74,25 +95,35 @@ impl Device {
     /// let ctx = device.create_context();
     /// ```
     pub fn create_context(&self) -> Context {
-        let ptr = unsafe {
-            ffi_new!(
-                cuCtxCreate_v2,
-                CUctx_flags_enum::CU_CTX_SCHED_AUTO as u32,
-                self.device
-            )
-        }
-        .expect("Failed to create a new context");
-        if ptr.is_null() {
-            panic!("Cannot crate a new context");
-        }
-        let ptr_new = ctx_pop().unwrap();
-        assert_eq!(ptr, ptr_new);
-        Arc::new(ContextOwned { ptr })
+        self.context_builder()
+            .build()
+            .expect("Failed to create a new context")
+    }
+}
+
+bitflags::bitflags! {
+    /// Flags of CUDA context, see [cuCtxCreate]
+    ///
+    /// Empty flags corresponds to `CU_CTX_SCHED_AUTO`.
+    ///
+    /// [cuCtxCreate]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__CTX.html#group__CUDA__CTX_1g65dc0012348bc84810e2103a40d8e2cf
+    pub struct ContextFlags: u32 {
+        /// Actively spin while waiting results from the GPU
+        const SCHED_SPIN = 0x01;
+        /// Yield the thread while waiting results from the GPU
+        const SCHED_YIELD = 0x02;
+        /// Block the thread on a synchronization primitive while waiting results from the GPU
+        const SCHED_BLOCKING_SYNC = 0x04;
+        /// Support mapped pinned allocations
+        const MAP_HOST = 0x08;
+        /// Do not reduce local memory after resizing local memory for a kernel
+        const LMEM_RESIZE_TO_MAX = 0x10;
     }
 }
 
 /// Push to the context stack of this thread
 fn ctx_push(ptr: CUcontext) -> Result<()> {
+    registry::check_alive(ptr)?;
     unsafe { ffi_call!(cuCtxPushCurrent_v2, ptr) }?;
     Ok(())
 }
@@ -108,6 +139,7 @@ fn ctx_pop() -> Result<CUcontext> {
 
 /// Get API version
 fn ctx_version(ptr: CUcontext) -> Result<u32> {
+    registry::check_alive(ptr)?;
     let mut version: u32 = 0;
     unsafe { ffi_call!(cuCtxGetApiVersion, ptr, &mut version as *mut _) }?;
     Ok(version)
@@ -130,22 +162,40 @@ pub trait Contexted {
     /// Get a reference
     ///
     /// This is **NOT** a Rust reference, i.e. you can drop owned context while the reference exists.
-    /// The reference becomes expired after owned context is released, and it will cause a runtime error.
+    /// The reference becomes expired after owned context is released,
+    /// and using it returns `AccelError::ContextExpired`.
     ///
     fn get_ref(&self) -> ContextRef;
+
+    /// Get the device of the context
+    fn get_device(&self) -> Result<Device> {
+        let device = unsafe { contexted_new!(self, cuCtxGetDevice)? };
+        Ok(Device { device })
+    }
 }
 
 /// Owend handler for CUDA context
 #[derive(Debug, PartialEq)]
 pub struct ContextOwned {
     ptr: CUcontext,
+    /// Device whose primary context is retained by this handler
+    primary: Option<CUdevice>,
 }
 
 pub type Context = Arc<ContextOwned>;
 
 impl Drop for ContextOwned {
     fn drop(&mut self) {
-        if let Err(e) = unsafe { ffi_call!(cuCtxDestroy_v2, self.ptr) } {
+        if registry::unregister(self.ptr) {
+            // pooled streams must be destroyed while the context is alive
+            crate::stream::StreamPool::release(ContextRef { ptr: self.ptr });
+            registry::mark_destroyed(self.ptr);
+        }
+        let result = match self.primary {
+            Some(device) => unsafe { ffi_call!(cuDevicePrimaryCtxRelease, device) },
+            None => unsafe { ffi_call!(cuCtxDestroy_v2, self.ptr) },
+        };
+        if let Err(e) = result {
             log::error!("Context remove failed: {:?}", e);
         }
     }
@@ -183,7 +233,7 @@ impl Contexted for Context {
 ///
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ContextRef {
-    ptr: CUcontext,
+    pub(crate) ptr: CUcontext,
 }
 
 impl ContextRef {
@@ -282,23 +332,27 @@ mod tests {
         Ok(())
     }
 
-    #[should_panic]
     #[test]
-    fn expired_context_ref() {
-        let device = Device::nth(0).unwrap();
+    fn expired_context_ref() -> Result<()> {
+        let device = Device::nth(0)?;
         let ctx = device.create_context();
         let ctx_ref = ctx.get_ref();
         drop(ctx);
-        let _version = ctx_ref.version().unwrap(); // ctx has been expired
+        // ctx has been expired
+        assert!(matches!(ctx_ref.version(), Err(AccelError::ContextExpired)));
+        Ok(())
     }
 
-    #[should_panic]
     #[test]
-    fn expired_contexted_call() {
-        let device = Device::nth(0).unwrap();
+    fn expired_contexted_call() -> Result<()> {
+        let device = Device::nth(0)?;
         let ctx = device.create_context();
         let ctx_ref = ctx.get_ref();
         drop(ctx);
-        unsafe { contexted_call!(&ctx_ref, cuCtxSynchronize) }.unwrap();
+        assert!(matches!(
+            unsafe { contexted_call!(&ctx_ref, cuCtxSynchronize) },
+            Err(AccelError::ContextExpired)
+        ));
+        Ok(())
     }
 }
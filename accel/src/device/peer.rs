@@ -0,0 +1,104 @@
+//! [Peer access] between contexts on different devices
+//!
+//! [Peer access]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__PEER__ACCESS.html
+
+use super::*;
+
+impl Device {
+    /// Check if this device can access memories on `peer` device directly
+    pub fn can_access_peer(&self, peer: &Device) -> Result<bool> {
+        let mut can_access = 0;
+        unsafe {
+            ffi_call!(
+                cuDeviceCanAccessPeer,
+                &mut can_access as *mut _,
+                self.device,
+                peer.device
+            )?;
+        }
+        Ok(can_access != 0)
+    }
+}
+
+fn ctx_can_access_peer(ctx: ContextRef, peer: &impl Contexted) -> Result<bool> {
+    ctx.get_device()?.can_access_peer(&peer.get_device()?)
+}
+
+fn ctx_enable_peer_access(ctx: ContextRef, peer: &impl Contexted) -> Result<()> {
+    match unsafe { contexted_call!(&ctx, cuCtxEnablePeerAccess, peer.get_ref().ptr, 0) } {
+        Err(AccelError::CUDAError {
+            error: cudaError_enum::CUDA_ERROR_PEER_ACCESS_ALREADY_ENABLED,
+            ..
+        }) => Ok(()),
+        result => result,
+    }
+}
+
+fn ctx_disable_peer_access(ctx: ContextRef, peer: &impl Contexted) -> Result<()> {
+    unsafe { contexted_call!(&ctx, cuCtxDisablePeerAccess, peer.get_ref().ptr) }
+}
+
+macro_rules! impl_peer_access {
+    ($ctx:ty) => {
+        impl $ctx {
+            /// Check if this context can access memories in `peer` context
+            ///
+            /// Contexts have separate address spaces even on the same device,
+            /// and this returns the answer of the driver for their devices.
+            /// Memories in different contexts are copied by `cuMemcpyPeer` regardless of this.
+            pub fn can_access_peer(&self, peer: &impl Contexted) -> Result<bool> {
+                ctx_can_access_peer(ContextRef { ptr: self.ptr }, peer)
+            }
+
+            /// Enable this context to access memories allocated in `peer` context
+            ///
+            /// This is unidirectional, i.e. `peer` cannot access memories in this context
+            /// until `peer.enable_peer_access(self)` is called.
+            /// Calling this twice is allowed.
+            pub fn enable_peer_access(&self, peer: &impl Contexted) -> Result<()> {
+                ctx_enable_peer_access(ContextRef { ptr: self.ptr }, peer)
+            }
+
+            /// Disable access to `peer` context enabled by `enable_peer_access`
+            pub fn disable_peer_access(&self, peer: &impl Contexted) -> Result<()> {
+                ctx_disable_peer_access(ContextRef { ptr: self.ptr }, peer)
+            }
+        }
+    };
+}
+
+impl_peer_access!(ContextOwned);
+impl_peer_access!(ContextRef);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_device() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx1 = device.create_context();
+        let ctx2 = device.create_context();
+        assert_eq!(
+            ctx1.can_access_peer(&ctx2)?,
+            device.can_access_peer(&device)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn enable_peer_access() -> Result<()> {
+        if Device::get_count()? < 2 {
+            return Ok(()); // requires multi-GPU host
+        }
+        let ctx0 = Device::nth(0)?.create_context();
+        let ctx1 = Device::nth(1)?.create_context();
+        if !ctx0.can_access_peer(&ctx1)? {
+            return Ok(());
+        }
+        ctx0.enable_peer_access(&ctx1)?;
+        ctx0.enable_peer_access(&ctx1)?; // twice
+        ctx0.disable_peer_access(&ctx1)?;
+        Ok(())
+    }
+}
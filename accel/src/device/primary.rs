@@ -0,0 +1,112 @@
+//! [Primary context] shared with CUDA Runtime API and other libraries
+//!
+//! [Primary context]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__PRIMARY__CTX.html
+
+use super::*;
+
+/// State of the primary context of a device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrimaryContextState {
+    pub flags: ContextFlags,
+    /// Primary context is retained by someone, i.e. its flags cannot be changed
+    pub active: bool,
+}
+
+impl Device {
+    /// Retain the primary context of this device
+    ///
+    /// The primary context is unique for each device, and shared with CUDA Runtime API.
+    /// Memories allocated on this context can be used by libraries using Runtime API.
+    /// It is released (not destroyed) when the returned handler is dropped.
+    ///
+    /// ```
+    /// # use accel::*;
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx1 = device.primary_context().unwrap();
+    /// let ctx2 = device.primary_context().unwrap();
+    /// assert_eq!(ctx1.get_ref(), ctx2.get_ref());
+    /// ```
+    pub fn primary_context(&self) -> Result<Context> {
+        let ptr = unsafe { ffi_new!(cuDevicePrimaryCtxRetain, self.device)? };
+        registry::register(ptr);
+        Ok(Arc::new(ContextOwned {
+            ptr,
+            primary: Some(self.device),
+        }))
+    }
+
+    /// Get flags and activity of the primary context
+    pub fn primary_context_state(&self) -> Result<PrimaryContextState> {
+        let mut flags = 0;
+        let mut active = 0;
+        unsafe {
+            ffi_call!(
+                cuDevicePrimaryCtxGetState,
+                self.device,
+                &mut flags as *mut _,
+                &mut active as *mut _
+            )?;
+        }
+        Ok(PrimaryContextState {
+            flags: ContextFlags::from_bits_truncate(flags),
+            active: active != 0,
+        })
+    }
+
+    /// Set flags of the primary context
+    ///
+    /// This must be called before the primary context becomes active,
+    /// i.e. before `primary_context` or any Runtime API call on this device.
+    pub fn set_primary_context_flags(&self, flags: ContextFlags) -> Result<()> {
+        unsafe { ffi_call!(cuDevicePrimaryCtxSetFlags, self.device, flags.bits()) }?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_flags() {
+        // (typed flag, raw flag)
+        let table = [
+            (ContextFlags::empty(), CUctx_flags::CU_CTX_SCHED_AUTO),
+            (ContextFlags::SCHED_SPIN, CUctx_flags::CU_CTX_SCHED_SPIN),
+            (ContextFlags::SCHED_YIELD, CUctx_flags::CU_CTX_SCHED_YIELD),
+            (
+                ContextFlags::SCHED_BLOCKING_SYNC,
+                CUctx_flags::CU_CTX_SCHED_BLOCKING_SYNC,
+            ),
+            (ContextFlags::MAP_HOST, CUctx_flags::CU_CTX_MAP_HOST),
+            (
+                ContextFlags::LMEM_RESIZE_TO_MAX,
+                CUctx_flags::CU_CTX_LMEM_RESIZE_TO_MAX,
+            ),
+            (ContextFlags::all(), CUctx_flags::CU_CTX_FLAGS_MASK),
+        ];
+        for &(flags, raw) in &table {
+            assert_eq!(flags.bits(), raw as u32);
+        }
+    }
+
+    #[test]
+    fn primary_context() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.primary_context()?;
+        assert!(device.primary_context_state()?.active);
+        ctx.sync()?;
+        let created = device.create_context();
+        assert_ne!(ctx.get_ref(), created.get_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn primary_context_memory() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.primary_context()?;
+        let mem = DeviceMemory::<i32>::from_elem(&ctx, 12, 1);
+        assert_eq!(mem.as_slice(), &[1; 12]);
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! Process-wide registry of contexts created or retained by accel
+//!
+//! [ContextRef] does not own the context, and using it after the context is destroyed
+//! causes undefined behavior in CUDA Driver API.
+//! Every `ContextOwned` registers its pointer while it is alive,
+//! and the pointer is recorded as destroyed when the last `ContextOwned` releases it.
+//! Destroyed pointers are rejected before pushing them to the context stack.
+//!
+//! Contexts unknown to the registry, e.g. created by other libraries or the CUDA Runtime API,
+//! are not rejected since accel cannot know their lifetime.
+//!
+//! Be sure that the check is based on the pointer value,
+//! i.e. a stale reference becomes valid again if the driver reuses the address for a new context.
+//!
+//! [ContextRef]: ./struct.ContextRef.html
+
+use super::*;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+#[derive(Default)]
+struct Registry {
+    /// Number of `ContextOwned` for each pointer, which can be larger than one for primary contexts
+    live: HashMap<usize, usize>,
+    /// Pointers destroyed or released by accel, and not reused by the driver yet
+    destroyed: HashSet<usize>,
+}
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+fn registry() -> MutexGuard<'static, Registry> {
+    // The registry is always consistent even if another thread panics
+    REGISTRY
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Register a context created or retained by accel
+pub(super) fn register(ptr: CUcontext) {
+    let mut registry = registry();
+    registry.destroyed.remove(&(ptr as usize));
+    *registry.live.entry(ptr as usize).or_insert(0) += 1;
+}
+
+/// Unregister a context which will be destroyed or released
+///
+/// Returns `true` if this is the last handle of the context, which is checked and removed under one lock.
+/// Then the context is treated as unknown, i.e. resources in it can be cleaned up,
+/// until `mark_destroyed` is called.
+pub(super) fn unregister(ptr: CUcontext) -> bool {
+    let mut registry = registry();
+    match registry.live.get_mut(&(ptr as usize)) {
+        Some(count) if *count > 1 => {
+            *count -= 1;
+            false
+        }
+        Some(_) => {
+            registry.live.remove(&(ptr as usize));
+            true
+        }
+        None => {
+            log::error!("Unregistered context is released: {:?}", ptr);
+            false
+        }
+    }
+}
+
+/// Record that the context is destroyed or released after the last handle is unregistered
+///
+/// This is ignored if the context has been registered again, e.g. a primary context retained by another thread.
+pub(super) fn mark_destroyed(ptr: CUcontext) {
+    let mut registry = registry();
+    if !registry.live.contains_key(&(ptr as usize)) {
+        registry.destroyed.insert(ptr as usize);
+    }
+}
+
+/// Check the context is created or retained by accel, and not destroyed yet
+pub(crate) fn is_live(ptr: CUcontext) -> bool {
+    registry().live.contains_key(&(ptr as usize))
+}
+
+/// Check the context has not been destroyed or released by accel
+pub(super) fn check_alive(ptr: CUcontext) -> Result<()> {
+    if registry().destroyed.contains(&(ptr as usize)) {
+        Err(AccelError::ContextExpired)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fake pointers which never be returned by the driver
+    fn fake_context(id: usize) -> CUcontext {
+        (usize::MAX - id) as CUcontext
+    }
+
+    #[test]
+    fn register_unregister() {
+        let ptr = fake_context(0);
+        register(ptr);
+        assert!(check_alive(ptr).is_ok());
+        assert!(is_live(ptr));
+        assert!(unregister(ptr));
+        // not destroyed until marked
+        assert!(check_alive(ptr).is_ok());
+        assert!(!is_live(ptr));
+        mark_destroyed(ptr);
+        assert!(check_alive(ptr).is_err());
+        assert!(!is_live(ptr));
+    }
+
+    /// Unregister the last handle and destroy it
+    fn release(ptr: CUcontext) {
+        assert!(unregister(ptr));
+        mark_destroyed(ptr);
+    }
+
+    #[test]
+    fn retained_twice() {
+        let ptr = fake_context(1);
+        register(ptr);
+        register(ptr);
+        assert!(!unregister(ptr));
+        assert!(check_alive(ptr).is_ok());
+        assert!(is_live(ptr));
+        release(ptr);
+        assert!(check_alive(ptr).is_err());
+    }
+
+    #[test]
+    fn unknown_context() {
+        // e.g. created by another library
+        let ptr = fake_context(3);
+        assert!(check_alive(ptr).is_ok());
+        assert!(!is_live(ptr));
+    }
+
+    #[test]
+    fn reused_address() {
+        let ptr = fake_context(4);
+        register(ptr);
+        release(ptr);
+        assert!(check_alive(ptr).is_err());
+        // the driver creates a new context at the same address
+        register(ptr);
+        assert!(check_alive(ptr).is_ok());
+        release(ptr);
+    }
+
+    #[test]
+    fn retained_while_releasing() {
+        let ptr = fake_context(5);
+        register(ptr);
+        assert!(unregister(ptr));
+        // e.g. another thread retains the primary context again
+        register(ptr);
+        mark_destroyed(ptr);
+        assert!(check_alive(ptr).is_ok());
+        assert!(is_live(ptr));
+        release(ptr);
+    }
+
+    #[test]
+    fn expired_ref() {
+        let ptr = fake_context(2);
+        register(ptr);
+        let ctx_ref = ContextRef { ptr };
+        release(ptr);
+        // These never touch the driver
+        assert!(matches!(ctx_ref.guard(), Err(AccelError::ContextExpired)));
+        assert!(matches!(ctx_ref.sync(), Err(AccelError::ContextExpired)));
+        assert!(matches!(ctx_ref.version(), Err(AccelError::ContextExpired)));
+        assert!(matches!(
+            unsafe { contexted_call!(&ctx_ref, cuCtxSynchronize) },
+            Err(AccelError::ContextExpired)
+        ));
+    }
+}
@@ -0,0 +1,329 @@
+//! Select a device on multi-GPU hosts
+
+use super::*;
+
+/// Environment variable to restrict visible devices, e.g. `ACCEL_VISIBLE_DEVICES=0,2`
+pub const VISIBLE_DEVICES_ENV: &str = "ACCEL_VISIBLE_DEVICES";
+
+/// Description of a device used by [DeviceSelector]
+///
+/// [DeviceSelector]: ./struct.DeviceSelector.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDescription {
+    /// Ordinal of the device, i.e. the argument of `Device::nth`
+    pub id: usize,
+    pub name: String,
+    pub compute_capability: ComputeCapability,
+    /// Free memory in bytes, which is `None` unless queried by `query_with_free_memory`
+    pub free_memory: Option<usize>,
+    /// Total memory in bytes
+    pub total_memory: usize,
+}
+
+impl DeviceDescription {
+    /// Query the description of a device without its free memory size
+    pub fn query(id: usize, device: &Device) -> Result<Self> {
+        Ok(DeviceDescription {
+            id,
+            name: device.get_name()?,
+            compute_capability: device.compute_capability()?,
+            free_memory: None,
+            total_memory: device.total_memory()?,
+        })
+    }
+
+    /// Query the description of a device with its free memory size
+    ///
+    /// This retains the primary context of the device to get its free memory size,
+    /// and returns an error if the context is not available, e.g. on an exclusive-process GPU used by others.
+    pub fn query_with_free_memory(id: usize, device: &Device) -> Result<Self> {
+        let desc = Self::query(id, device)?;
+        let ctx = device.primary_context()?;
+        Ok(DeviceDescription {
+            free_memory: Some(query_free_memory(&ctx)?),
+            ..desc
+        })
+    }
+}
+
+/// Policy to choose one device from candidates
+///
+/// ```
+/// # use accel::*;
+/// let device = DeviceSelector::new()
+///     .min_compute_capability(ComputeCapability::new(3, 0))
+///     .most_free_memory()
+///     .select()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceSelector {
+    min_compute_capability: Option<ComputeCapability>,
+    name_pattern: Option<String>,
+    visible_devices: Option<Vec<usize>>,
+    most_free_memory: bool,
+}
+
+impl DeviceSelector {
+    /// Selector accepts any device, and choose the first one
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject devices whose compute capability is less than `cc`
+    pub fn min_compute_capability(mut self, cc: ComputeCapability) -> Self {
+        self.min_compute_capability = Some(cc);
+        self
+    }
+
+    /// Accept devices whose name contains `pattern` (case insensitive)
+    pub fn name_contains(mut self, pattern: &str) -> Self {
+        self.name_pattern = Some(pattern.to_lowercase());
+        self
+    }
+
+    /// Accept only devices of given ordinals
+    pub fn visible_devices(mut self, ids: &[usize]) -> Self {
+        self.visible_devices = Some(ids.to_vec());
+        self
+    }
+
+    /// Accept only devices listed in `ACCEL_VISIBLE_DEVICES` environment variable
+    ///
+    /// All devices are visible if the variable is not set.
+    pub fn visible_devices_from_env(self) -> Result<Self> {
+        match std::env::var(VISIBLE_DEVICES_ENV) {
+            Ok(value) => Ok(self.visible_devices(&parse_visible_devices(&value)?)),
+            Err(_) => Ok(self),
+        }
+    }
+
+    /// Choose a device with the largest free memory instead of the first one
+    ///
+    /// `select` retains the primary context of each candidate device to query its free memory.
+    pub fn most_free_memory(mut self) -> Self {
+        self.most_free_memory = true;
+        self
+    }
+
+    fn accept(&self, desc: &DeviceDescription) -> bool {
+        if let Some(cc) = self.min_compute_capability {
+            if desc.compute_capability < cc {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.name_pattern {
+            if !desc.name.to_lowercase().contains(pattern.as_str()) {
+                return false;
+            }
+        }
+        if let Some(ids) = &self.visible_devices {
+            if !ids.contains(&desc.id) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Choose a device from candidates, and returns its ordinal
+    ///
+    /// `count` of `AccelError::DeviceNotMatched` is the number of `candidates`.
+    /// Candidates whose free memory is not queried are chosen last by `most_free_memory`.
+    pub fn select_from(&self, candidates: &[DeviceDescription]) -> Result<usize> {
+        let accepted = candidates.iter().filter(|desc| self.accept(desc));
+        let found = if self.most_free_memory {
+            // `max_by_key` returns the last maximum, we prefer smaller ordinal
+            accepted.rev().max_by_key(|desc| desc.free_memory)
+        } else {
+            accepted.min_by_key(|desc| desc.id)
+        };
+        match found {
+            Some(desc) => Ok(desc.id),
+            None => Err(AccelError::DeviceNotMatched {
+                selector: format!("{:?}", self),
+                count: candidates.len(),
+            }),
+        }
+    }
+
+    /// Choose a device from all devices on this host
+    ///
+    /// `count` of `AccelError::DeviceNotMatched` is the number of all devices.
+    pub fn select(&self) -> Result<Device> {
+        let devices = Device::all()?;
+        let mut candidates = Vec::new();
+        for (id, device) in devices.iter().enumerate() {
+            // Skip querying devices which are rejected without description
+            if let Some(ids) = &self.visible_devices {
+                if !ids.contains(&id) {
+                    continue;
+                }
+            }
+            candidates.push(if self.most_free_memory {
+                DeviceDescription::query_with_free_memory(id, device)?
+            } else {
+                DeviceDescription::query(id, device)?
+            });
+        }
+        match self.select_from(&candidates) {
+            Ok(id) => Device::nth(id),
+            Err(AccelError::DeviceNotMatched { selector, .. }) => {
+                Err(AccelError::DeviceNotMatched {
+                    selector,
+                    count: devices.len(),
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Parse comma-separated device ordinals, e.g. `"0,2"`
+///
+/// An empty string means no devices are visible.
+pub fn parse_visible_devices(value: &str) -> Result<Vec<usize>> {
+    if value.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    value
+        .split(',')
+        .map(|id| {
+            id.trim()
+                .parse()
+                .map_err(|_| AccelError::InvalidVisibleDevices {
+                    value: value.into(),
+                })
+        })
+        .collect()
+}
+
+impl Device {
+    /// Get all devices on this host
+    pub fn all() -> Result<Vec<Self>> {
+        (0..Self::get_count()?).map(Self::nth).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: usize = 1024 * 1024 * 1024;
+
+    fn desc(id: usize, name: &str, cc: (u32, u32), free_gb: usize) -> DeviceDescription {
+        DeviceDescription {
+            id,
+            name: name.into(),
+            compute_capability: ComputeCapability::new(cc.0, cc.1),
+            free_memory: Some(free_gb * GB),
+            total_memory: 16 * GB,
+        }
+    }
+
+    fn host() -> Vec<DeviceDescription> {
+        vec![
+            desc(0, "GeForce GTX 1080", (6, 1), 4),
+            desc(1, "Tesla V100-SXM2-16GB", (7, 0), 12),
+            desc(2, "GeForce RTX 2080 Ti", (7, 5), 8),
+            desc(3, "Tesla V100-SXM2-16GB", (7, 0), 12),
+        ]
+    }
+
+    #[test]
+    fn select_from() -> Result<()> {
+        // (selector, expected ordinal)
+        let table = vec![
+            (DeviceSelector::new(), 0),
+            (
+                DeviceSelector::new().min_compute_capability(ComputeCapability::new(7, 0)),
+                1,
+            ),
+            (
+                DeviceSelector::new().min_compute_capability(ComputeCapability::new(7, 5)),
+                2,
+            ),
+            (DeviceSelector::new().name_contains("rtx"), 2),
+            (DeviceSelector::new().name_contains("Tesla"), 1),
+            (DeviceSelector::new().most_free_memory(), 1),
+            (DeviceSelector::new().visible_devices(&[3, 2]), 2),
+            (
+                DeviceSelector::new()
+                    .visible_devices(&[0, 2, 3])
+                    .most_free_memory(),
+                3,
+            ),
+            (
+                DeviceSelector::new()
+                    .name_contains("geforce")
+                    .most_free_memory(),
+                2,
+            ),
+        ];
+        for (selector, expected) in table {
+            assert_eq!(selector.select_from(&host())?, expected, "{:?}", selector);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn select_from_without_free_memory() -> Result<()> {
+        let mut host = host();
+        host[1].free_memory = None;
+        host[3].free_memory = None;
+        assert_eq!(
+            DeviceSelector::new()
+                .most_free_memory()
+                .select_from(&host)?,
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_from_no_match() {
+        let table = vec![
+            DeviceSelector::new().min_compute_capability(ComputeCapability::new(8, 0)),
+            DeviceSelector::new().name_contains("Radeon"),
+            DeviceSelector::new().visible_devices(&[4]),
+            DeviceSelector::new()
+                .name_contains("GTX")
+                .min_compute_capability(ComputeCapability::new(7, 0)),
+        ];
+        for selector in table {
+            match selector.select_from(&host()) {
+                Err(AccelError::DeviceNotMatched { count, .. }) => assert_eq!(count, 4),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+        assert!(DeviceSelector::new().select_from(&[]).is_err());
+    }
+
+    #[test]
+    fn parse_visible_devices() -> Result<()> {
+        assert_eq!(super::parse_visible_devices("0")?, vec![0]);
+        assert_eq!(super::parse_visible_devices("1,3")?, vec![1, 3]);
+        assert_eq!(super::parse_visible_devices(" 2 , 0 ")?, vec![2, 0]);
+        assert_eq!(super::parse_visible_devices("")?, Vec::<usize>::new());
+        assert!(super::parse_visible_devices("0,a").is_err());
+        assert!(super::parse_visible_devices("-1").is_err());
+        assert!(super::parse_visible_devices("0,,1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        let device = DeviceSelector::new().most_free_memory().select()?;
+        assert!(Device::all()?.contains(&device));
+        Ok(())
+    }
+
+    #[test]
+    fn select_no_match() -> Result<()> {
+        let count = Device::get_count()?;
+        match DeviceSelector::new().visible_devices(&[]).select() {
+            Err(AccelError::DeviceNotMatched { count: c, .. }) => assert_eq!(c, count),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        Ok(())
+    }
+}
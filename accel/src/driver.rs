@@ -0,0 +1,123 @@
+//! Version of installed CUDA Driver
+//!
+//! Some features are only available on newer drivers, e.g. stream-ordered allocation requires CUDA 11.2.
+//! Check it by `require_driver` before using them to get a descriptive error:
+//!
+//! ```
+//! use accel::*;
+//! let version = driver_version().unwrap();
+//! println!("CUDA Driver {}", version);
+//! require_driver(DriverVersion::new(10, 0)).unwrap();
+//! ```
+
+use crate::{error::*, *};
+use cuda::*;
+use std::fmt;
+
+/// Version of CUDA Driver API, e.g. `11.2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DriverVersion {
+    pub fn new(major: u32, minor: u32) -> Self {
+        DriverVersion { major, minor }
+    }
+
+    /// Decode an integer returned by `cuDriverGetVersion`, e.g. `11020` is `11.2`
+    pub fn from_raw(raw: u32) -> Self {
+        DriverVersion {
+            major: raw / 1000,
+            minor: (raw % 1000) / 10,
+        }
+    }
+
+    /// Encode into an integer in the same manner as `CUDA_VERSION` macro
+    pub fn to_raw(&self) -> u32 {
+        self.major * 1000 + self.minor * 10
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Get the version of installed CUDA Driver
+pub fn driver_version() -> Result<DriverVersion> {
+    let raw: i32 = unsafe { ffi_new!(cuDriverGetVersion)? };
+    Ok(DriverVersion::from_raw(raw as u32))
+}
+
+/// Check the installed driver is `required` or newer
+pub fn require_driver(required: DriverVersion) -> Result<()> {
+    check_driver(required, driver_version()?)
+}
+
+fn check_driver(required: DriverVersion, installed: DriverVersion) -> Result<()> {
+    if installed < required {
+        Err(AccelError::DriverTooOld {
+            required,
+            installed,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_raw() {
+        // (raw, major, minor)
+        let table = [
+            (9020, 9, 2),
+            (10000, 10, 0),
+            (10020, 10, 2),
+            (11000, 11, 0),
+            (11020, 11, 2),
+            (12010, 12, 1),
+        ];
+        for &(raw, major, minor) in &table {
+            let version = DriverVersion::from_raw(raw);
+            assert_eq!(version, DriverVersion::new(major, minor));
+            assert_eq!(version.to_raw(), raw);
+        }
+    }
+
+    #[test]
+    fn order() {
+        assert!(DriverVersion::new(11, 2) > DriverVersion::new(11, 0));
+        assert!(DriverVersion::new(11, 0) > DriverVersion::new(10, 2));
+        assert!(DriverVersion::new(10, 2) < DriverVersion::new(12, 0));
+    }
+
+    #[test]
+    fn check() {
+        let required = DriverVersion::new(11, 2);
+        assert!(check_driver(required, DriverVersion::new(11, 2)).is_ok());
+        assert!(check_driver(required, DriverVersion::new(12, 0)).is_ok());
+        match check_driver(required, DriverVersion::new(11, 0)) {
+            Err(AccelError::DriverTooOld {
+                required: r,
+                installed: i,
+            }) => {
+                assert_eq!(r, required);
+                assert_eq!(i, DriverVersion::new(11, 0));
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn version() -> Result<()> {
+        let version = driver_version()?;
+        assert!(version >= DriverVersion::from_raw(CUDA_VERSION));
+        Ok(())
+    }
+}
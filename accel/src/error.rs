@@ -1,3 +1,4 @@
+use crate::{device::ContextFlags, driver::DriverVersion};
 use cuda::cudaError_enum as DeviceError;
 use std::path::PathBuf;
 
@@ -23,11 +24,71 @@ pub enum AccelError {
     #[error("No device found for given ID")]
     DeviceNotFound { id: usize, count: usize },
 
+    #[error("Driver reports invalid value {value} for {attribute}")]
+    InvalidAttributeValue { attribute: String, value: i32 },
+
+    #[error("No device matches to {selector} in {count} devices")]
+    DeviceNotMatched { selector: String, count: usize },
+
+    #[error("Invalid visible devices: {value:?}")]
+    InvalidVisibleDevices { value: String },
+
+    #[error("Invalid device identifier: {value:?}")]
+    InvalidDeviceId { value: String },
+
+    #[error("CUDA context has been expired")]
+    ContextExpired,
+
+    #[error("Failed to create a context: {reason}")]
+    ContextCreationFailed { reason: String },
+
+    #[error("Invalid context flags {flags:?}: {reason}")]
+    InvalidContextFlags {
+        flags: ContextFlags,
+        reason: &'static str,
+    },
+
+    #[error("CUDA Driver {installed} is installed, but {required} or later is required")]
+    DriverTooOld {
+        required: DriverVersion,
+        installed: DriverVersion,
+    },
+
+    #[error("Stream priority {priority} is out of range [{greatest}, {least}]")]
+    InvalidStreamPriority {
+        priority: i32,
+        least: i32,
+        greatest: i32,
+    },
+
+    #[error("Host callback has panicked: {message}")]
+    HostCallbackPanicked { message: String },
+
+    #[error("Host callbacks cannot be captured into a graph")]
+    CallbackInCapture,
+
+    #[error("Event is created without timing")]
+    EventTimingDisabled,
+
+    #[error(
+        "Invalid elapsed time {millis} ms, the start event may be recorded after the end event"
+    )]
+    InvalidElapsedTime { millis: f32 },
+
+    #[error("Shared memory carveout {percent}% is out of range [0, 100]")]
+    InvalidCarveout { percent: u8 },
+
+    #[error("Invalid IPC handle: {reason}")]
+    InvalidIpcHandle { reason: String },
+
+    #[error("Invalid launch configuration: {reason}")]
+    InvalidLaunchConfig { reason: String },
+
+    #[error("Invalid occupancy limits: {reason}")]
+    InvalidOccupancyLimits { reason: String },
+
     #[error("File not found: {path:?}")]
     FileNotFound { path: PathBuf },
-
-    #[error(transparent)]
-    AsyncTaskFailed(#[from] tokio::task::JoinError),
 }
 
 /// Convert return code of CUDA Driver/Runtime API into Result
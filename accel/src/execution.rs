@@ -77,6 +77,40 @@
 //! Be sure that this sub-module will be generated where the `f` is defined.
 //! `get_kernel` and default implementation of `launch` are separated to keep unsafe codes in this crate.
 //!
+//! Launch on a stream
+//! ------------------
+//!
+//! `launch` blocks until the kernel completes, and `launch_async` uses a stream of the pool internally.
+//! `launch_on` enqueues the kernel into a given stream without synchronization,
+//! which is useful to chain kernels and memcpy in a stream,
+//! and to express dependencies between streams by `Event` and `Stream::wait_event`.
+//! This is unsafe since the arguments are not borrowed until the kernel completes:
+//!
+//! ```
+//! use accel::*;
+//!
+//! #[kernel]
+//! unsafe fn add1(a: *mut i32) {
+//!     *a += 1;
+//! }
+//!
+//! # fn main() -> error::Result<()> {
+//! let device = Device::nth(0)?;
+//! let ctx = device.create_context();
+//! let module = add1::Module::new(&ctx)?;
+//! let stream = Stream::new(ctx.get_ref());
+//! let mut a = DeviceMemory::<i32>::zeros(&ctx, 1);
+//! unsafe {
+//!     module.launch_on(&stream, 1, 1, (&mut a,))?;
+//!     module.launch_on(&stream, 1, 1, (&mut a,))?; // runs after the first one
+//! }
+//! // `a` must not be accessed until here
+//! stream.sync()?;
+//! assert_eq!(a[0], 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! [DeviceSend]: trait.DeviceSend.html
 //! [accel::kernel]: ../attr.kernel.html
 //! [Module]: ../module/struct.Module.html
@@ -85,6 +119,148 @@ use crate::{contexted_call, device::*, error::*, *};
 use cuda::*;
 use std::{ffi::*, ptr::null_mut};
 
+/// Configuration of a kernel launch
+///
+/// Launched into the null stream and synchronized if `stream` is `None`,
+/// or enqueued into `stream` without synchronization otherwise.
+///
+/// ```
+/// # use accel::*;
+/// # let device = Device::nth(0).unwrap();
+/// # let ctx = device.create_context();
+/// let stream = Stream::new(ctx.get_ref());
+/// let config = LaunchConfig::new(64, 256)
+///     .shared_mem(256 * std::mem::size_of::<f32>() as u32)
+///     .on(&stream);
+/// assert_eq!(config.grid, Grid::x(64));
+/// assert_eq!(config.block, Block::x(256));
+/// assert_eq!(config.shared_mem_bytes, 1024);
+///
+/// // grid and block
+/// let config: LaunchConfig = ((64, 2), 256).into();
+/// assert_eq!(config.grid, Grid::xy(64, 2));
+/// assert_eq!(config.shared_mem_bytes, 0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LaunchConfig<'stream> {
+    pub grid: Grid,
+    pub block: Block,
+    /// Size of dynamic shared memory per block, i.e. `extern __shared__` arrays
+    pub shared_mem_bytes: u32,
+    pub stream: Option<&'stream Stream>,
+}
+
+impl<'stream> LaunchConfig<'stream> {
+    /// Launch without dynamic shared memory on the null stream
+    pub fn new(grid: impl Into<Grid>, block: impl Into<Block>) -> Self {
+        LaunchConfig {
+            grid: grid.into(),
+            block: block.into(),
+            shared_mem_bytes: 0,
+            stream: None,
+        }
+    }
+
+    /// Set the size of dynamic shared memory per block
+    pub fn shared_mem(mut self, bytes: u32) -> Self {
+        self.shared_mem_bytes = bytes;
+        self
+    }
+
+    /// Enqueue into `stream` instead of the null stream
+    pub fn on(mut self, stream: &'stream Stream) -> Self {
+        self.stream = Some(stream);
+        self
+    }
+
+    /// Check grid, block and dynamic shared memory against the limits of a device
+    ///
+    /// Dynamic shared memory is checked against the opt-in limit of the device,
+    /// which a kernel can use only after `Kernel::set_max_dynamic_shared_memory`.
+    /// Use `validate_for` to check against the limit of a kernel.
+    ///
+    /// ```
+    /// # use accel::*;
+    /// # let device = Device::nth(0).unwrap();
+    /// let attr = device.cached_attributes().unwrap();
+    /// assert!(LaunchConfig::new(64, 256).validate(&attr).is_ok());
+    /// // 4096 threads per block
+    /// assert!(LaunchConfig::new(1, (64, 64, 1)).validate(&attr).is_err());
+    /// ```
+    pub fn validate(&self, attr: &DeviceAttributes) -> Result<()> {
+        let invalid = |reason: String| Err(AccelError::InvalidLaunchConfig { reason });
+        let block = self.block;
+        let max_block = attr.max_block_dim;
+        if block.x == 0 || block.y == 0 || block.z == 0 {
+            return invalid(format!("Block {:?} has a zero dimension", block));
+        }
+        if block.x > max_block.x || block.y > max_block.y || block.z > max_block.z {
+            return invalid(format!(
+                "Block ({}, {}, {}) exceeds the maximum block dimension ({}, {}, {})",
+                block.x, block.y, block.z, max_block.x, max_block.y, max_block.z
+            ));
+        }
+        let threads = block.x as u64 * block.y as u64 * block.z as u64;
+        if threads > attr.max_threads_per_block as u64 {
+            return invalid(format!(
+                "{} threads per block exceeds the maximum threads per block {}",
+                threads, attr.max_threads_per_block
+            ));
+        }
+        let grid = self.grid;
+        let max_grid = attr.max_grid_dim;
+        if grid.x == 0 || grid.y == 0 || grid.z == 0 {
+            return invalid(format!("Grid {:?} has a zero dimension", grid));
+        }
+        if grid.x > max_grid.x || grid.y > max_grid.y || grid.z > max_grid.z {
+            return invalid(format!(
+                "Grid ({}, {}, {}) exceeds the maximum grid dimension ({}, {}, {})",
+                grid.x, grid.y, grid.z, max_grid.x, max_grid.y, max_grid.z
+            ));
+        }
+        // Kernels can use shared memory up to the opt-in limit by `Kernel::set_max_dynamic_shared_memory`
+        let max_shared_memory = std::cmp::max(
+            attr.max_shared_memory_per_block,
+            attr.max_shared_memory_per_block_optin,
+        );
+        if self.shared_mem_bytes as usize > max_shared_memory {
+            return invalid(format!(
+                "Dynamic shared memory {} bytes exceeds the maximum shared memory per block {} bytes",
+                self.shared_mem_bytes, max_shared_memory
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check against the limits of the device of `kernel`, and the dynamic shared memory limit of `kernel`
+    ///
+    /// This queries the driver, and is not run for successful launches.
+    /// Call this explicitly to check a configuration beforehand.
+    /// A launch rejected by the driver also runs this check,
+    /// so that a violation is reported as `AccelError::InvalidLaunchConfig` instead of `CUDA_ERROR_INVALID_VALUE`.
+    /// Other kernel-specific limits, e.g. registers and static shared memory, are still checked by the driver.
+    pub fn validate_for(&self, kernel: &Kernel) -> Result<()> {
+        self.validate(&kernel.get_device()?.cached_attributes()?)?;
+        let max_shared_memory = kernel.max_dynamic_shared_memory()?;
+        if self.shared_mem_bytes as usize > max_shared_memory {
+            return Err(AccelError::InvalidLaunchConfig {
+                reason: format!(
+                    "Dynamic shared memory {} bytes exceeds the maximum of the kernel {} bytes, \
+                     see Kernel::set_max_dynamic_shared_memory",
+                    self.shared_mem_bytes, max_shared_memory
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<G: Into<Grid>, B: Into<Block>> From<(G, B)> for LaunchConfig<'_> {
+    fn from((grid, block): (G, B)) -> Self {
+        LaunchConfig::new(grid, block)
+    }
+}
+
 /// Type which can be sent to device
 pub trait DeviceSend {
     /// Type on device
@@ -143,3 +319,97 @@ impl_device_send!(f32);
 impl_device_send!(f64);
 
 accel_derive::define_launchable!(12 /* 0..=12 */);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{turing_attributes as turing, PTX_DO_NOTHING};
+
+    #[test]
+    fn validate_ok() -> Result<()> {
+        let attr = turing();
+        let configs = [
+            LaunchConfig::new(1, 1),
+            LaunchConfig::new(2147483647, 1024),
+            LaunchConfig::new((1, 65535, 65535), (1, 1, 64)),
+            LaunchConfig::new(64, (32, 32)),
+            LaunchConfig::new(64, (16, 8, 8)),
+            LaunchConfig::new(64, 256).shared_mem(64 * 1024),
+        ];
+        for config in &configs {
+            config.validate(&attr)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate_error() {
+        let attr = turing();
+        // (config, part of the reason)
+        let table = [
+            (LaunchConfig::new(1, 0), "Block"),
+            (LaunchConfig::new(1, (1, 0, 1)), "Block"),
+            (LaunchConfig::new(1, 1025), "maximum block dimension"),
+            (LaunchConfig::new(1, (1, 1, 65)), "maximum block dimension"),
+            (LaunchConfig::new(1, (64, 64, 1)), "4096 threads per block"),
+            (LaunchConfig::new(1, (32, 32, 2)), "2048 threads per block"),
+            (LaunchConfig::new(0, 1), "Grid"),
+            (LaunchConfig::new((1, 1, 0), 1), "Grid"),
+            (
+                LaunchConfig::new(2147483648_u32, 1),
+                "maximum grid dimension",
+            ),
+            (LaunchConfig::new((1, 65536), 1), "maximum grid dimension"),
+            (
+                LaunchConfig::new(1, 1).shared_mem(64 * 1024 + 1),
+                "shared memory",
+            ),
+        ];
+        for (config, expected) in &table {
+            match config.validate(&attr) {
+                Err(AccelError::InvalidLaunchConfig { reason }) => {
+                    assert!(reason.contains(expected), "{}", reason)
+                }
+                result => panic!("Unexpected result for {:?}: {:?}", config, result),
+            }
+        }
+    }
+
+    #[test]
+    fn validate_for() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+        LaunchConfig::new(1, 1)
+            .shared_mem(48 * 1024)
+            .validate_for(&kernel)?;
+        // the kernel has not opted in to more than 48KB
+        let config = LaunchConfig::new(1, 1).shared_mem(48 * 1024 + 4);
+        match config.validate_for(&kernel) {
+            Err(AccelError::InvalidLaunchConfig { reason }) => {
+                assert!(
+                    reason.contains("set_max_dynamic_shared_memory"),
+                    "{}",
+                    reason
+                )
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        let optin = device.attributes()?.max_shared_memory_per_block_optin;
+        if optin > 48 * 1024 {
+            kernel.set_max_dynamic_shared_memory(optin)?;
+            config.validate_for(&kernel)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn validate_overflow() {
+        // threads per block is computed without overflow
+        let mut attr = turing();
+        attr.max_block_dim = Block::xyz(u32::MAX, u32::MAX, u32::MAX);
+        let config = LaunchConfig::new(1, (65536, 65536, 1));
+        assert!(config.validate(&attr).is_err());
+    }
+}
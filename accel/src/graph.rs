@@ -0,0 +1,423 @@
+//! [CUDA Graph] to launch a sequence of tasks with a single CPU call
+//!
+//! A graph is recorded from tasks issued into a stream between `Stream::begin_capture` and `Stream::end_capture`,
+//! or built explicitly from kernel and memcpy nodes.
+//! A graph is instantiated into [GraphExec] once, and it can be launched many times
+//! with much smaller CPU overhead than issuing each task.
+//!
+//! ```
+//! # use accel::*;
+//! let device = Device::nth(0).unwrap();
+//! let ctx = device.create_context();
+//! let stream = Stream::new(ctx.get_ref());
+//!
+//! stream.begin_capture().unwrap();
+//! // ... issue tasks into `stream`, e.g. by `launch_on` ...
+//! let graph = stream.end_capture().unwrap();
+//!
+//! let exec = graph.instantiate().unwrap();
+//! for _ in 0..3 {
+//!     // memories and kernel arguments used in the tasks are alive
+//!     unsafe { exec.launch(&stream) }.unwrap();
+//! }
+//! stream.sync().unwrap();
+//! ```
+//!
+//! Graphs keep raw pointers of memories and kernel arguments, i.e. they must outlive the graph.
+//! Host callbacks cannot be captured, since `Stream::add_callback` runs the callback only once.
+//!
+//! [CUDA Graph]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__GRAPH.html
+//! [GraphExec]: ./struct.GraphExec.html
+
+use crate::{contexted_call, contexted_new, device::*, error::*, *};
+use cuda::*;
+use std::{ffi::c_void, ptr::null_mut};
+
+/// How the capture in a stream restricts potentially unsafe API calls, e.g. `cuMemAlloc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Prohibit unsafe calls in any thread while any thread is capturing in global mode
+    Global,
+    /// Prohibit unsafe calls only in the thread which begins the capture (default)
+    ThreadLocal,
+    /// No restriction
+    Relaxed,
+}
+
+impl Default for CaptureMode {
+    fn default() -> Self {
+        CaptureMode::ThreadLocal
+    }
+}
+
+impl CaptureMode {
+    pub(crate) fn raw(self) -> CUstreamCaptureMode {
+        match self {
+            CaptureMode::Global => CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_GLOBAL,
+            CaptureMode::ThreadLocal => CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL,
+            CaptureMode::Relaxed => CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_RELAXED,
+        }
+    }
+}
+
+impl Stream {
+    /// Start recording tasks issued into this stream into a graph instead of executing them
+    pub fn begin_capture(&self) -> Result<()> {
+        self.begin_capture_with_mode(CaptureMode::default())
+    }
+
+    pub fn begin_capture_with_mode(&self, mode: CaptureMode) -> Result<()> {
+        unsafe { contexted_call!(self, cuStreamBeginCapture_v2, self.stream, mode.raw()) }
+    }
+
+    /// Finish recording, and get the graph of tasks issued since `Stream::begin_capture`
+    pub fn end_capture(&self) -> Result<Graph> {
+        let mut graph = null_mut();
+        unsafe {
+            contexted_call!(self, cuStreamEndCapture, self.stream, &mut graph as *mut _)?;
+        }
+        Ok(Graph {
+            graph,
+            context: self.get_ref(),
+        })
+    }
+}
+
+/// Node in a [Graph], which is used to specify dependencies of another node
+///
+/// [Graph]: ./struct.Graph.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphNode(CUgraphNode);
+
+/// Graph of tasks, which is recorded from a stream or built explicitly
+#[derive(Debug, Contexted)]
+pub struct Graph {
+    graph: CUgraph,
+    context: ContextRef,
+}
+
+unsafe impl Send for Graph {}
+
+impl Drop for Graph {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuGraphDestroy, self.graph) } {
+            log::error!("Failed to destroy CUDA graph: {:?}", e);
+        }
+    }
+}
+
+impl Graph {
+    /// Create an empty graph to add nodes explicitly
+    pub fn new(context: ContextRef) -> Result<Self> {
+        let graph = unsafe { contexted_new!(&context, cuGraphCreate, 0)? };
+        Ok(Graph { graph, context })
+    }
+
+    /// Number of nodes in this graph
+    pub fn num_nodes(&self) -> Result<usize> {
+        let mut n = 0;
+        unsafe {
+            contexted_call!(
+                self,
+                cuGraphGetNodes,
+                self.graph,
+                null_mut(),
+                &mut n as *mut _
+            )?;
+        }
+        Ok(n)
+    }
+
+    /// Add a kernel launch node executed after `dependencies`
+    ///
+    /// `args` are pointers to kernel arguments given by `DeviceSend::as_kernel_parameter`,
+    /// and they are copied into the node.
+    /// `config.stream` is ignored since the node runs in the stream where the graph is launched.
+    ///
+    /// Safety
+    /// ------
+    /// - The types of `args` must match the kernel signature
+    /// - Memories pointed by `args` must outlive this graph and its instantiations
+    pub unsafe fn add_kernel_node<'stream>(
+        &mut self,
+        kernel: &Kernel,
+        config: impl Into<LaunchConfig<'stream>>,
+        args: &mut [*mut c_void],
+        dependencies: &[GraphNode],
+    ) -> Result<GraphNode> {
+        let LaunchConfig {
+            grid,
+            block,
+            shared_mem_bytes,
+            ..
+        } = config.into();
+        let params = CUDA_KERNEL_NODE_PARAMS {
+            func: kernel.func,
+            gridDimX: grid.x,
+            gridDimY: grid.y,
+            gridDimZ: grid.z,
+            blockDimX: block.x,
+            blockDimY: block.y,
+            blockDimZ: block.z,
+            sharedMemBytes: shared_mem_bytes,
+            kernelParams: args.as_mut_ptr(),
+            extra: null_mut(),
+        };
+        let node = contexted_new!(
+            self,
+            cuGraphAddKernelNode,
+            self.graph,
+            dependencies.as_ptr() as *const CUgraphNode,
+            dependencies.len(),
+            &params as *const _
+        )?;
+        Ok(GraphNode(node))
+    }
+
+    /// Add a memcpy node from `src` to `dst` executed after `dependencies`
+    ///
+    /// Both memories must be managed by CUDA, e.g. `DeviceMemory` or `PageLockedMemory`,
+    /// since the driver determines memory types from pointers in the unified address space.
+    ///
+    /// Safety
+    /// ------
+    /// - `dst` and `src` must outlive this graph and its instantiations
+    pub unsafe fn add_memcpy_node<T: Scalar>(
+        &mut self,
+        dst: &mut (impl Memory<Elem = T> + ?Sized),
+        src: &(impl Memory<Elem = T> + ?Sized),
+        dependencies: &[GraphNode],
+    ) -> Result<GraphNode> {
+        assert_eq!(dst.num_elem(), src.num_elem());
+        let params = memcpy_params(
+            dst.head_addr_mut() as CUdeviceptr,
+            src.head_addr() as CUdeviceptr,
+            dst.num_elem() * T::size_of(),
+        );
+        let node = contexted_new!(
+            self,
+            cuGraphAddMemcpyNode,
+            self.graph,
+            dependencies.as_ptr() as *const CUgraphNode,
+            dependencies.len(),
+            &params as *const _,
+            self.context.ptr
+        )?;
+        Ok(GraphNode(node))
+    }
+
+    /// Instantiate this graph to launch
+    pub fn instantiate(&self) -> Result<GraphExec> {
+        let mut log = vec![0_u8; 1024];
+        let mut error_node = null_mut();
+        let exec = unsafe {
+            contexted_new!(
+                self,
+                cuGraphInstantiate,
+                self.graph,
+                &mut error_node as *mut _,
+                log.as_mut_ptr() as *mut _,
+                log.len()
+            )
+        };
+        if exec.is_err() {
+            let len = log.iter().position(|&b| b == 0).unwrap_or(log.len());
+            log::error!(
+                "Failed to instantiate CUDA graph: {}",
+                String::from_utf8_lossy(&log[..len])
+            );
+        }
+        Ok(GraphExec {
+            exec: exec?,
+            context: self.context,
+        })
+    }
+}
+
+/// 1D memcpy in unified address space, where the driver determines memory types from pointers
+fn memcpy_params(dst: CUdeviceptr, src: CUdeviceptr, byte_count: usize) -> CUDA_MEMCPY3D {
+    CUDA_MEMCPY3D {
+        srcXInBytes: 0,
+        srcY: 0,
+        srcZ: 0,
+        srcLOD: 0,
+        srcMemoryType: CUmemorytype::CU_MEMORYTYPE_UNIFIED,
+        srcHost: std::ptr::null(),
+        srcDevice: src,
+        srcArray: null_mut(),
+        reserved0: null_mut(),
+        srcPitch: 0,
+        srcHeight: 0,
+        dstXInBytes: 0,
+        dstY: 0,
+        dstZ: 0,
+        dstLOD: 0,
+        dstMemoryType: CUmemorytype::CU_MEMORYTYPE_UNIFIED,
+        dstHost: null_mut(),
+        dstDevice: dst,
+        dstArray: null_mut(),
+        reserved1: null_mut(),
+        dstPitch: 0,
+        dstHeight: 0,
+        WidthInBytes: byte_count,
+        Height: 1,
+        Depth: 1,
+    }
+}
+
+/// Executable graph instantiated by `Graph::instantiate`
+#[derive(Debug, Contexted)]
+pub struct GraphExec {
+    exec: CUgraphExec,
+    context: ContextRef,
+}
+
+unsafe impl Send for GraphExec {}
+
+impl Drop for GraphExec {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuGraphExecDestroy, self.exec) } {
+            log::error!("Failed to destroy CUDA graph executable: {:?}", e);
+        }
+    }
+}
+
+impl GraphExec {
+    /// Launch all tasks in the graph into `stream` without synchronization
+    ///
+    /// Safety
+    /// ------
+    /// - Memories and kernel arguments recorded into the graph must be alive
+    ///   until the launched tasks complete, e.g. by `Stream::sync`
+    pub unsafe fn launch(&self, stream: &Stream) -> Result<()> {
+        contexted_call!(self, cuGraphLaunch, self.exec, stream.stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::PTX_DO_NOTHING;
+
+    #[test]
+    fn capture_mode_raw() {
+        // (mode, raw)
+        let table = [
+            (
+                CaptureMode::Global,
+                CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_GLOBAL,
+            ),
+            (
+                CaptureMode::ThreadLocal,
+                CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_THREAD_LOCAL,
+            ),
+            (
+                CaptureMode::Relaxed,
+                CUstreamCaptureMode::CU_STREAM_CAPTURE_MODE_RELAXED,
+            ),
+        ];
+        for &(mode, raw) in &table {
+            assert_eq!(mode.raw(), raw);
+        }
+        assert_eq!(CaptureMode::default(), CaptureMode::ThreadLocal);
+    }
+
+    #[test]
+    fn memcpy_params_1d() {
+        let params = memcpy_params(0x1000, 0x2000, 48);
+        assert_eq!(params.dstDevice, 0x1000);
+        assert_eq!(params.srcDevice, 0x2000);
+        assert_eq!(params.WidthInBytes, 48);
+        assert_eq!((params.Height, params.Depth), (1, 1));
+    }
+
+    #[test]
+    fn capture_replay() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let stream = Stream::new(ctx.get_ref());
+        let mut src = DeviceMemory::<u32>::from_elem(&ctx, 12, 3);
+        let mut dst = DeviceMemory::<u32>::zeros(&ctx, 12);
+
+        stream.begin_capture()?;
+        assert!(stream.is_capturing()?);
+        unsafe {
+            contexted_call!(
+                &stream,
+                cuMemcpyDtoDAsync_v2,
+                dst.head_addr_mut() as CUdeviceptr,
+                src.head_addr() as CUdeviceptr,
+                12 * std::mem::size_of::<u32>(),
+                stream.stream
+            )?;
+        }
+        let graph = stream.end_capture()?;
+        assert!(!stream.is_capturing()?);
+        assert_eq!(graph.num_nodes()?, 1);
+        // nothing executed while capturing
+        stream.sync()?;
+        assert_eq!(dst.as_slice(), &[0; 12]);
+
+        let exec = graph.instantiate()?;
+        for i in 0..3 {
+            src.set(i);
+            unsafe { exec.launch(&stream)? };
+            stream.sync()?;
+            assert_eq!(dst.as_slice(), &[i; 12]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn explicit_nodes() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+        let src = DeviceMemory::<u32>::from_elem(&ctx, 12, 3);
+        let mut dst = DeviceMemory::<u32>::zeros(&ctx, 12);
+
+        let mut graph = Graph::new(ctx.get_ref())?;
+        let launch = unsafe { graph.add_kernel_node(&kernel, (1, 1), &mut [], &[])? };
+        let _copy = unsafe { graph.add_memcpy_node(&mut *dst, &*src, &[launch])? };
+        assert_eq!(graph.num_nodes()?, 2);
+
+        let exec = graph.instantiate()?;
+        let stream = Stream::new(ctx.get_ref());
+        unsafe { exec.launch(&stream)? };
+        stream.sync()?;
+        assert_eq!(dst.as_slice(), &[3; 12]);
+        Ok(())
+    }
+
+    #[test]
+    fn kernel_node_shared_memory() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+
+        let mut graph = Graph::new(ctx.get_ref())?;
+        let config = LaunchConfig::new(2, 32).shared_mem(1024);
+        let node = unsafe { graph.add_kernel_node(&kernel, config, &mut [], &[])? };
+        let mut params = std::mem::MaybeUninit::uninit();
+        let params = unsafe {
+            contexted_call!(
+                &graph,
+                cuGraphKernelNodeGetParams,
+                node.0,
+                params.as_mut_ptr()
+            )?;
+            params.assume_init()
+        };
+        assert_eq!(params.sharedMemBytes, 1024);
+        assert_eq!(params.gridDimX, 2);
+        assert_eq!(params.blockDimX, 32);
+
+        let exec = graph.instantiate()?;
+        let stream = Stream::new(ctx.get_ref());
+        unsafe { exec.launch(&stream)? };
+        stream.sync()?;
+        Ok(())
+    }
+}
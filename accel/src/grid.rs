@@ -1,3 +1,4 @@
+use crate::{error::*, Block};
 use num_traits::ToPrimitive;
 
 /// Size of Grid (grid of blocks) in [CUDA thread hierarchy]( http://docs.nvidia.com/cuda/cuda-c-programming-guide/index.html#programming-model )
@@ -54,6 +55,23 @@ use num_traits::ToPrimitive;
 /// assert_eq!(grid3d.y, 128);
 /// assert_eq!(grid3d.z, 256);
 /// ```
+///
+/// - Covering a problem shape
+///
+/// ```
+/// # use accel::*;
+/// // 1000 threads in blocks of 256 threads
+/// let grid1d = Grid::cover(1000, 256);
+/// assert_eq!(grid1d, Grid::x(4));
+///
+/// // 1920x1080 image in 16x16 tiles
+/// let grid2d = Grid::cover_xy(1920, 1080, (16, 16));
+/// assert_eq!(grid2d, Grid::xy(120, 68));
+///
+/// // Error instead of panic for a zero block or too many blocks
+/// assert!(Grid::try_cover(1000, 0).is_err());
+/// assert!(Grid::try_cover_xy(usize::MAX, 1, (1, 1)).is_err());
+/// ```
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Grid {
     pub x: u32,
@@ -100,6 +118,90 @@ impl Grid {
             z: z.to_u32().expect("Cannot convert to u32"),
         }
     }
+
+    /// Smallest 1D Grid of `block` which covers `n` threads along x-axis
+    ///
+    /// Panic
+    /// -----
+    /// - If `block.x` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn cover(n: usize, block: impl Into<Block>) -> Self {
+        Self::try_cover(n, block).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Smallest 2D Grid of `block` which covers `width x height` threads
+    ///
+    /// Panic
+    /// -----
+    /// - If `block.x` or `block.y` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn cover_xy(width: usize, height: usize, block: impl Into<Block>) -> Self {
+        Self::try_cover_xy(width, height, block).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Smallest 3D Grid of `block` which covers `width x height x depth` threads
+    ///
+    /// Panic
+    /// -----
+    /// - If any dimension of `block` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn cover_xyz(width: usize, height: usize, depth: usize, block: impl Into<Block>) -> Self {
+        Self::try_cover_xyz(width, height, depth, block).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same as `Grid::cover`, but returns `AccelError::InvalidLaunchConfig` instead of panic
+    pub fn try_cover(n: usize, block: impl Into<Block>) -> Result<Self> {
+        let block = block.into();
+        Ok(Grid {
+            x: cover_dim(n, block.x)?,
+            y: 1,
+            z: 1,
+        })
+    }
+
+    /// Same as `Grid::cover_xy`, but returns `AccelError::InvalidLaunchConfig` instead of panic
+    pub fn try_cover_xy(width: usize, height: usize, block: impl Into<Block>) -> Result<Self> {
+        let block = block.into();
+        Ok(Grid {
+            x: cover_dim(width, block.x)?,
+            y: cover_dim(height, block.y)?,
+            z: 1,
+        })
+    }
+
+    /// Same as `Grid::cover_xyz`, but returns `AccelError::InvalidLaunchConfig` instead of panic
+    pub fn try_cover_xyz(
+        width: usize,
+        height: usize,
+        depth: usize,
+        block: impl Into<Block>,
+    ) -> Result<Self> {
+        let block = block.into();
+        Ok(Grid {
+            x: cover_dim(width, block.x)?,
+            y: cover_dim(height, block.y)?,
+            z: cover_dim(depth, block.z)?,
+        })
+    }
+}
+
+/// Number of blocks of `threads` threads required to cover `n` threads, i.e. `ceil(n / threads)`
+fn checked_cover_dim(n: usize, threads: u32) -> Option<u32> {
+    if threads == 0 {
+        return None;
+    }
+    let threads = threads.to_usize()?;
+    // `usize::div_ceil` is not available on the pinned toolchain
+    (n.checked_add(threads - 1)? / threads).to_u32()
+}
+
+fn cover_dim(n: usize, threads: u32) -> Result<u32> {
+    checked_cover_dim(n, threads).ok_or_else(|| AccelError::InvalidLaunchConfig {
+        reason: format!(
+            "Cannot cover {} threads by blocks of {} threads",
+            n, threads
+        ),
+    })
 }
 
 impl<I: ToPrimitive> Into<Grid> for (I,) {
@@ -142,3 +244,80 @@ impl_into_grid!(i32);
 impl_into_grid!(i64);
 impl_into_grid!(i128);
 impl_into_grid!(isize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cover() {
+        // (n, threads, blocks)
+        let table = [
+            (0, 256, 0),
+            (1, 256, 1),
+            (255, 256, 1),
+            (256, 256, 1),
+            (257, 256, 2),
+            (1000, 256, 4),
+            (1024, 1, 1024),
+            (u32::MAX as usize, 1, u32::MAX),
+            (u32::MAX as usize + 1, 2, 1 << 31),
+        ];
+        for &(n, threads, blocks) in &table {
+            assert_eq!(
+                checked_cover_dim(n, threads),
+                Some(blocks),
+                "{}/{}",
+                n,
+                threads
+            );
+        }
+    }
+
+    #[test]
+    fn cover_overflow() {
+        assert_eq!(checked_cover_dim(1, 0), None);
+        assert_eq!(checked_cover_dim(u32::MAX as usize + 1, 1), None);
+        assert_eq!(checked_cover_dim(usize::MAX, 1), None);
+        // `n + threads - 1` overflows
+        assert_eq!(checked_cover_dim(usize::MAX, 2), None);
+    }
+
+    #[should_panic(expected = "Cannot cover")]
+    #[test]
+    fn cover_zero_block() {
+        let _grid = Grid::cover(1, 0);
+    }
+
+    #[test]
+    fn try_cover() {
+        assert_eq!(Grid::try_cover(1000, 256).unwrap(), Grid::x(4));
+        assert_eq!(
+            Grid::try_cover_xy(1920, 1080, (16, 16)).unwrap(),
+            Grid::xy(120, 68)
+        );
+        let errors = [
+            Grid::try_cover(1, 0),
+            Grid::try_cover(u32::MAX as usize + 1, 1),
+            Grid::try_cover_xy(1, 1, (1, 0)),
+            Grid::try_cover_xy(1, usize::MAX, (1, 2)),
+            Grid::try_cover_xyz(1, 1, 1, (1, 1, 0)),
+            Grid::try_cover_xyz(1, 1, usize::MAX, (1, 1, 1)),
+        ];
+        for result in &errors {
+            match result {
+                Err(AccelError::InvalidLaunchConfig { reason }) => {
+                    assert!(reason.contains("Cannot cover"), "{}", reason)
+                }
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn cover_xyz() {
+        assert_eq!(Grid::cover_xyz(100, 50, 10, (8, 8, 4)), Grid::xyz(13, 7, 3));
+        // Only x-axis is covered
+        assert_eq!(Grid::cover(100, (8, 8, 4)), Grid::x(13));
+    }
+}
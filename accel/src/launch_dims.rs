@@ -0,0 +1,187 @@
+use crate::*;
+
+/// Pair of Grid and Block which covers a problem shape
+///
+/// Each thread is expected to process one element, and to skip out-of-bounds indices
+/// since the last block along each axis may be partially filled.
+///
+/// Examples
+/// --------
+///
+/// ```
+/// # use accel::*;
+/// let dims = LaunchDims::for_1d(1000);
+/// assert_eq!(dims.grid, Grid::x(4));
+/// assert_eq!(dims.block, Block::x(256));
+///
+/// let dims = LaunchDims::for_2d(1920, 1080, 16);
+/// assert_eq!(dims.grid, Grid::xy(120, 68));
+/// assert_eq!(dims.block, Block::xy(16, 16));
+/// ```
+///
+/// - From the shape of an array
+///
+/// ```
+/// # use accel::*;
+/// let dims: LaunchDims = Ix2::new(1920, 1080).into();
+/// assert_eq!(dims, LaunchDims::for_2d(1920, 1080, 16));
+///
+/// let config: LaunchConfig = dims.into();
+/// assert_eq!(config.grid, Grid::xy(120, 68));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaunchDims {
+    pub grid: Grid,
+    pub block: Block,
+}
+
+impl LaunchDims {
+    /// Number of threads per block used by `for_1d`
+    pub const BLOCK_1D: u32 = 256;
+    /// Tile size used for the conversion from `Ix2`, i.e. 16x16 threads per block
+    pub const TILE_2D: u32 = 16;
+    /// Tile size used for the conversion from `Ix3`, i.e. 8x8x8 threads per block
+    pub const TILE_3D: u32 = 8;
+
+    /// Cover `n` threads by 1D blocks of `LaunchDims::BLOCK_1D` threads
+    ///
+    /// Panic
+    /// -----
+    /// - If the number of blocks does not fit in u32
+    pub fn for_1d(n: usize) -> Self {
+        Self::cover_1d(n, Self::BLOCK_1D)
+    }
+
+    /// Cover `n` threads by 1D blocks of `block` threads
+    ///
+    /// Panic
+    /// -----
+    /// - If `block` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn cover_1d(n: usize, block: u32) -> Self {
+        let block = Block::x(block);
+        LaunchDims {
+            grid: Grid::cover(n, block),
+            block,
+        }
+    }
+
+    /// Cover `width x height` threads by `tile x tile` blocks
+    ///
+    /// Panic
+    /// -----
+    /// - If `tile` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn for_2d(width: usize, height: usize, tile: u32) -> Self {
+        let block = Block::xy(tile, tile);
+        LaunchDims {
+            grid: Grid::cover_xy(width, height, block),
+            block,
+        }
+    }
+
+    /// Cover `width x height x depth` threads by `tile x tile x tile` blocks
+    ///
+    /// Panic
+    /// -----
+    /// - If `tile` is zero
+    /// - If the number of blocks does not fit in u32
+    pub fn for_3d(width: usize, height: usize, depth: usize, tile: u32) -> Self {
+        let block = Block::xyz(tile, tile, tile);
+        LaunchDims {
+            grid: Grid::cover_xyz(width, height, depth, block),
+            block,
+        }
+    }
+}
+
+// Channels are not counted, i.e. one thread processes one "CUDA array element"
+
+impl From<Ix1> for LaunchDims {
+    fn from(dim: Ix1) -> Self {
+        LaunchDims::for_1d(dim.width)
+    }
+}
+
+impl From<Ix2> for LaunchDims {
+    fn from(dim: Ix2) -> Self {
+        LaunchDims::for_2d(dim.width, dim.height, LaunchDims::TILE_2D)
+    }
+}
+
+impl From<Ix3> for LaunchDims {
+    fn from(dim: Ix3) -> Self {
+        LaunchDims::for_3d(dim.width, dim.height, dim.depth, LaunchDims::TILE_3D)
+    }
+}
+
+impl From<LaunchDims> for LaunchConfig<'_> {
+    fn from(dims: LaunchDims) -> Self {
+        LaunchConfig::new(dims.grid, dims.block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn for_1d() {
+        // (n, grid)
+        let table = [(0, 0), (1, 1), (256, 1), (257, 2), (1 << 20, 4096)];
+        for &(n, grid) in &table {
+            let dims = LaunchDims::for_1d(n);
+            assert_eq!(dims.grid, Grid::x(grid), "n = {}", n);
+            assert_eq!(dims.block, Block::x(256));
+        }
+    }
+
+    #[test]
+    fn for_2d() {
+        let dims = LaunchDims::for_2d(100, 1, 32);
+        assert_eq!(dims.grid, Grid::xy(4, 1));
+        assert_eq!(dims.block, Block::xy(32, 32));
+    }
+
+    #[test]
+    fn for_3d() {
+        let dims = LaunchDims::for_3d(64, 65, 1, 4);
+        assert_eq!(dims.grid, Grid::xyz(16, 17, 1));
+        assert_eq!(dims.block, Block::xyz(4, 4, 4));
+    }
+
+    #[test]
+    fn from_dimension() {
+        assert_eq!(
+            LaunchDims::from(Ix1::new(1000)),
+            LaunchDims::cover_1d(1000, 256)
+        );
+        assert_eq!(
+            LaunchDims::from(Ix2::new(33, 17)),
+            LaunchDims {
+                grid: Grid::xy(3, 2),
+                block: Block::xy(16, 16),
+            }
+        );
+        assert_eq!(
+            LaunchDims::from(Ix3::new(9, 8, 7)),
+            LaunchDims {
+                grid: Grid::xyz(2, 1, 1),
+                block: Block::xyz(8, 8, 8),
+            }
+        );
+    }
+
+    #[test]
+    fn from_dimension_channels() {
+        let mut dim = Ix1::new(1000);
+        dim.num_channels = NumChannels::Four;
+        assert_eq!(LaunchDims::from(dim), LaunchDims::for_1d(1000));
+    }
+
+    #[should_panic(expected = "Cannot cover")]
+    #[test]
+    fn overflow() {
+        let _dims = LaunchDims::for_2d(usize::MAX, 1, 1);
+    }
+}
@@ -106,37 +106,127 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! Async/.await
+//! ------------
+//! Async memcpy and kernel launch return plain futures woken by a background thread of accel,
+//! i.e. they work on any executor, e.g. `futures::executor::block_on`, tokio, or async-std.
+//! `tokio` and `async-std` features enable [runtime] module to run blocking calls,
+//! e.g. synchronization of a context, on the thread pool of these runtimes for blocking tasks.
+//!
+//! [runtime]: ./runtime/index.html
 
 extern crate cuda_driver_sys as cuda;
 
 pub use accel_derive::kernel;
 
 pub mod device;
+pub mod driver;
 pub mod error;
 pub mod execution;
+pub mod graph;
 pub mod linker;
 pub mod memory;
 pub mod module;
+pub mod occupancy;
 pub mod profiler;
+pub mod runtime;
 pub mod stream;
 
 mod block;
 mod grid;
 mod instruction;
+mod launch_dims;
 
 pub use block::Block;
 pub use device::*;
+pub use driver::*;
 pub use execution::*;
+pub use graph::*;
 pub use grid::Grid;
 pub use instruction::Instruction;
+pub use launch_dims::LaunchDims;
 pub use linker::*;
 pub use memory::*;
 pub use module::*;
+pub use occupancy::*;
 pub use profiler::*;
 pub use stream::*;
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use crate::*;
+    use cuda::CUdevice_attribute_enum as Attr;
+    use std::collections::HashMap;
+
+    /// PTX of an empty kernel `do_nothing`, generated by do_nothing example in accel-derive
+    pub(crate) const PTX_DO_NOTHING: &str = r#"
+        .version 3.2
+        .target sm_30
+        .address_size 64
+        .visible .entry do_nothing()
+        {
+          ret;
+        }
+        "#;
+
+    /// Raw attributes of a Turing (sm_75) GPU, from which fixtures of other tests are derived
+    pub(crate) fn turing() -> HashMap<Attr, i32> {
+        [
+            (Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR, 7),
+            (Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR, 5),
+            (Attr::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT, 40),
+            (Attr::CU_DEVICE_ATTRIBUTE_WARP_SIZE, 32),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_BLOCK, 1024),
+            (
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR,
+                1024,
+            ),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_X, 1024),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Y, 1024),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_BLOCK_DIM_Z, 64),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_X, 2147483647),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Y, 65535),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_GRID_DIM_Z, 65535),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK, 49152),
+            (
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN,
+                65536,
+            ),
+            (
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR,
+                65536,
+            ),
+            (Attr::CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_BLOCK, 65536),
+            (
+                Attr::CU_DEVICE_ATTRIBUTE_MAX_REGISTERS_PER_MULTIPROCESSOR,
+                65536,
+            ),
+            (Attr::CU_DEVICE_ATTRIBUTE_TOTAL_CONSTANT_MEMORY, 65536),
+            (Attr::CU_DEVICE_ATTRIBUTE_L2_CACHE_SIZE, 4194304),
+            (Attr::CU_DEVICE_ATTRIBUTE_CLOCK_RATE, 1590000),
+            (Attr::CU_DEVICE_ATTRIBUTE_MEMORY_CLOCK_RATE, 5001000),
+            (Attr::CU_DEVICE_ATTRIBUTE_GLOBAL_MEMORY_BUS_WIDTH, 256),
+            (Attr::CU_DEVICE_ATTRIBUTE_ASYNC_ENGINE_COUNT, 3),
+            (Attr::CU_DEVICE_ATTRIBUTE_CONCURRENT_KERNELS, 1),
+            (Attr::CU_DEVICE_ATTRIBUTE_ECC_ENABLED, 0),
+            (Attr::CU_DEVICE_ATTRIBUTE_INTEGRATED, 0),
+            (Attr::CU_DEVICE_ATTRIBUTE_CAN_MAP_HOST_MEMORY, 1),
+            (Attr::CU_DEVICE_ATTRIBUTE_UNIFIED_ADDRESSING, 1),
+            (Attr::CU_DEVICE_ATTRIBUTE_MANAGED_MEMORY, 1),
+            (Attr::CU_DEVICE_ATTRIBUTE_STREAM_PRIORITIES_SUPPORTED, 1),
+        ]
+        .iter()
+        .cloned()
+        .collect()
+    }
+
+    /// Decoded attributes of `turing()`
+    pub(crate) fn turing_attributes() -> DeviceAttributes {
+        let table = turing();
+        DeviceAttributes::decode(|attr| Ok(table[&attr])).unwrap()
+    }
+
     /// Test accel_derive::kernel can be used in accel crate itself
     #[super::kernel]
     fn f() {}
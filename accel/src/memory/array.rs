@@ -93,7 +93,9 @@ impl<T: Scalar, Dim: Dimension> Memcpy<[T]> for Array<T, Dim> {
     fn copy_from_async<'a>(&'a mut self, src: &'a [T]) -> BoxFuture<'a, ()> {
         assert_ne!(self.head_addr(), src.head_addr());
         assert_eq!(self.num_elem(), src.num_elem());
-        let stream = stream::Stream::new(self.context.get_ref());
+        let stream = stream::StreamPool::for_context(self.context.get_ref())
+            .checkout()
+            .expect("Failed to get a stream from pool");
         unsafe {
             contexted_call!(
                 self,
@@ -138,7 +140,9 @@ impl<T: Scalar, Dim: Dimension> Memcpy<Array<T, Dim>> for [T] {
     fn copy_from_async<'a>(&'a mut self, src: &'a Array<T, Dim>) -> BoxFuture<'a, ()> {
         assert_ne!(self.head_addr(), src.head_addr());
         assert_eq!(self.num_elem(), src.num_elem());
-        let stream = stream::Stream::new(src.context.get_ref());
+        let stream = stream::StreamPool::for_context(src.context.get_ref())
+            .checkout()
+            .expect("Failed to get a stream from pool");
         unsafe {
             contexted_call!(
                 src,
@@ -86,41 +86,51 @@ impl<T: Scalar> Memory for DeviceMemory<T> {
     }
 
     fn set(&mut self, value: T) {
-        match T::size_of() {
-            1 => unsafe {
-                contexted_call!(
-                    self,
-                    cuMemsetD8_v2,
-                    self.head_addr_mut() as CUdeviceptr,
-                    value.to_le_u8().unwrap(),
-                    self.num_elem()
-                )
-            }
-            .expect("memset failed for 8-bit scalar"),
-            2 => unsafe {
-                contexted_call!(
-                    self,
-                    cuMemsetD16_v2,
-                    self.head_addr_mut() as CUdeviceptr,
-                    value.to_le_u16().unwrap(),
-                    self.num_elem()
-                )
-            }
-            .expect("memset failed for 16-bit scalar"),
-            4 => unsafe {
-                contexted_call!(
-                    self,
-                    cuMemsetD32_v2,
-                    self.head_addr_mut() as CUdeviceptr,
-                    value.to_le_u32().unwrap(),
-                    self.num_elem()
-                )
-            }
-            .expect("memset failed for 64-bit scalar"),
-            _ => {
-                unimplemented!("memset for Device memory is only supported for 8/16/32-bit scalars")
-            }
-        }
+        unsafe { memset(self, self.ptr, value, self.size, None) }.expect("memset failed");
+    }
+}
+
+/// Fill `n` elements from `ptr` with `value`, issued into `stream` if given
+///
+/// Panic
+/// -----
+/// - if `T` is not a 8/16/32-bit scalar
+pub(crate) unsafe fn memset<T: Scalar>(
+    ctx: &impl Contexted,
+    ptr: CUdeviceptr,
+    value: T,
+    n: usize,
+    stream: Option<CUstream>,
+) -> Result<()> {
+    match (T::size_of(), stream) {
+        (1, None) => contexted_call!(ctx, cuMemsetD8_v2, ptr, value.to_le_u8().unwrap(), n),
+        (1, Some(stream)) => contexted_call!(
+            ctx,
+            cuMemsetD8Async,
+            ptr,
+            value.to_le_u8().unwrap(),
+            n,
+            stream
+        ),
+        (2, None) => contexted_call!(ctx, cuMemsetD16_v2, ptr, value.to_le_u16().unwrap(), n),
+        (2, Some(stream)) => contexted_call!(
+            ctx,
+            cuMemsetD16Async,
+            ptr,
+            value.to_le_u16().unwrap(),
+            n,
+            stream
+        ),
+        (4, None) => contexted_call!(ctx, cuMemsetD32_v2, ptr, value.to_le_u32().unwrap(), n),
+        (4, Some(stream)) => contexted_call!(
+            ctx,
+            cuMemsetD32Async,
+            ptr,
+            value.to_le_u32().unwrap(),
+            n,
+            stream
+        ),
+        _ => unimplemented!("memset for Device memory is only supported for 8/16/32-bit scalars"),
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::{contexted_call, device::*};
+use crate::{contexted_call, device::*, error::Result};
 use cuda::*;
 
 /// Total and Free memory size of the device (in bytes)
@@ -9,22 +9,30 @@ struct MemoryInfo {
 }
 
 impl MemoryInfo {
-    fn get(ctx: Context) -> Self {
+    fn query(ctx: &Context) -> Result<Self> {
         let mut free = 0;
         let mut total = 0;
         unsafe {
             contexted_call!(
-                &ctx,
+                ctx,
                 cuMemGetInfo_v2,
                 &mut free as *mut usize,
                 &mut total as *mut usize
-            )
+            )?;
         }
-        .expect("Cannot get memory info");
-        MemoryInfo { free, total }
+        Ok(MemoryInfo { free, total })
+    }
+
+    fn get(ctx: Context) -> Self {
+        Self::query(&ctx).expect("Cannot get memory info")
     }
 }
 
+/// Get free memory size in bytes of the device of given context, or an error of the driver
+pub(crate) fn query_free_memory(ctx: &Context) -> Result<usize> {
+    Ok(MemoryInfo::query(ctx)?.free)
+}
+
 /// Get total memory size in bytes of the current device
 ///
 /// Panic
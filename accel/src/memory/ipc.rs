@@ -0,0 +1,542 @@
+//! Share device memories between processes
+//!
+//! A producer process allocates an [ExportableDeviceMemory] and exports an [IpcMemHandle] of it,
+//! sends the bytes of the handle to a consumer process by any IPC mechanism, e.g. a pipe or a socket,
+//! and the consumer opens the memory by `DeviceMemory::open_ipc`.
+//! CUDA IPC is only available on Linux.
+//!
+//! ```no_run
+//! # use accel::*;
+//! # let device = Device::nth(0).unwrap();
+//! # let context = device.create_context();
+//! // producer
+//! let mem = ExportableDeviceMemory::<f32>::zeros(&context, 128);
+//! let bytes = mem.ipc_handle().unwrap().to_bytes();
+//!
+//! // consumer in another process
+//! let handle = IpcMemHandle::<f32>::from_bytes(&bytes).unwrap();
+//! let mem = DeviceMemory::open_ipc(context.get_ref(), &handle).unwrap();
+//! let mut host = vec![1.0; 128];
+//! host.copy_from(&mem);
+//! ```
+//!
+//! [ExportableDeviceMemory]: ./struct.ExportableDeviceMemory.html
+//! [IpcMemHandle]: ./struct.IpcMemHandle.html
+
+use super::{
+    slice::{memcpy, memcpy_async},
+    *,
+};
+use crate::{error::*, *};
+use cuda::*;
+use futures::future::BoxFuture;
+use std::{convert::TryInto, fmt, marker::PhantomData, os::raw::c_void};
+
+/// Size of `CUipcMemHandle` and `CUipcEventHandle`
+pub(crate) const IPC_HANDLE_SIZE: usize = 64;
+
+/// Handle of a device memory shared with other processes
+///
+/// This keeps the number of elements and the element size in addition to the raw handle of CUDA,
+/// and `from_bytes` rejects bytes encoded for another element type.
+///
+/// ```
+/// # use accel::*;
+/// let handle = IpcMemHandle::<f32>::from_raw([1; 64], 128);
+/// let bytes = handle.to_bytes();
+/// assert_eq!(bytes.len(), IpcMemHandle::<f32>::ENCODED_SIZE);
+/// assert_eq!(IpcMemHandle::<f32>::from_bytes(&bytes).unwrap(), handle);
+/// assert!(IpcMemHandle::<f64>::from_bytes(&bytes).is_err());
+/// ```
+pub struct IpcMemHandle<T> {
+    raw: [u8; IPC_HANDLE_SIZE],
+    num_elem: usize,
+    phantom: PhantomData<T>,
+}
+
+impl<T> Clone for IpcMemHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for IpcMemHandle<T> {}
+
+impl<T> PartialEq for IpcMemHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw[..] == other.raw[..] && self.num_elem == other.num_elem
+    }
+}
+
+impl<T> Eq for IpcMemHandle<T> {}
+
+impl<T> fmt::Debug for IpcMemHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpcMemHandle")
+            .field("raw", &&self.raw[..])
+            .field("num_elem", &self.num_elem)
+            .finish()
+    }
+}
+
+impl<T> IpcMemHandle<T> {
+    /// Size of bytes encoded by `to_bytes`
+    ///
+    /// The layout is the raw handle, the number of elements in `u64`,
+    /// and the element size in `u32`, where integers are little endian.
+    pub const ENCODED_SIZE: usize = IPC_HANDLE_SIZE + 8 + 4;
+
+    pub fn from_raw(raw: [u8; IPC_HANDLE_SIZE], num_elem: usize) -> Self {
+        IpcMemHandle {
+            raw,
+            num_elem,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Raw handle of CUDA
+    pub fn raw(&self) -> [u8; IPC_HANDLE_SIZE] {
+        self.raw
+    }
+
+    /// Number of elements of the shared memory
+    pub fn num_elem(&self) -> usize {
+        self.num_elem
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::ENCODED_SIZE);
+        bytes.extend_from_slice(&self.raw);
+        bytes.extend_from_slice(&(self.num_elem as u64).to_le_bytes());
+        bytes.extend_from_slice(&(std::mem::size_of::<T>() as u32).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(AccelError::InvalidIpcHandle {
+                reason: format!(
+                    "{} bytes are given while {} bytes are expected",
+                    bytes.len(),
+                    Self::ENCODED_SIZE
+                ),
+            });
+        }
+        let (raw, rest) = bytes.split_at(IPC_HANDLE_SIZE);
+        let (num_elem, elem_size) = rest.split_at(8);
+        let num_elem = u64::from_le_bytes(num_elem.try_into().unwrap());
+        let elem_size = u32::from_le_bytes(elem_size.try_into().unwrap());
+        if elem_size as usize != std::mem::size_of::<T>() {
+            return Err(AccelError::InvalidIpcHandle {
+                reason: format!(
+                    "Element size is {} bytes while {} bytes are expected",
+                    elem_size,
+                    std::mem::size_of::<T>()
+                ),
+            });
+        }
+        // `TryFrom<&[u8]>` is not implemented for arrays larger than 32 on the pinned toolchain
+        let mut raw_array = [0_u8; IPC_HANDLE_SIZE];
+        raw_array.copy_from_slice(raw);
+        Ok(Self::from_raw(raw_array, num_elem as usize))
+    }
+}
+
+/// Device memory allocated by `cuMemAlloc`, which can be shared with other processes
+///
+/// [DeviceMemory] cannot be shared since it is a managed memory.
+/// This is not accessible from host as [IpcDeviceMemory].
+///
+/// [DeviceMemory]: ./struct.DeviceMemory.html
+/// [IpcDeviceMemory]: ./struct.IpcDeviceMemory.html
+#[derive(Contexted)]
+pub struct ExportableDeviceMemory<T> {
+    ptr: CUdeviceptr,
+    size: usize,
+    context: Context,
+    phantom: PhantomData<T>,
+}
+
+unsafe impl<T> Sync for ExportableDeviceMemory<T> {}
+unsafe impl<T> Send for ExportableDeviceMemory<T> {}
+
+impl<T> Drop for ExportableDeviceMemory<T> {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuMemFree_v2, self.ptr) } {
+            log::error!("Failed to free device memory: {:?}", e);
+        }
+    }
+}
+
+impl<T> fmt::Debug for ExportableDeviceMemory<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExportableDeviceMemory")
+            .field("context", &self.context)
+            .field("ptr", &self.ptr)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<T: Scalar> ExportableDeviceMemory<T> {
+    /// Export a handle to share this memory with other processes
+    pub fn ipc_handle(&self) -> Result<IpcMemHandle<T>> {
+        let handle = unsafe { contexted_new!(self, cuIpcGetMemHandle, self.ptr)? };
+        let mut raw = [0_u8; IPC_HANDLE_SIZE];
+        for (byte, &r) in raw.iter_mut().zip(handle.reserved.iter()) {
+            *byte = r as u8;
+        }
+        Ok(IpcMemHandle::from_raw(raw, self.size))
+    }
+}
+
+impl<T: Scalar> Memory for ExportableDeviceMemory<T> {
+    type Elem = T;
+    fn head_addr(&self) -> *const T {
+        self.ptr as _
+    }
+
+    fn head_addr_mut(&mut self) -> *mut T {
+        self.ptr as _
+    }
+
+    fn num_elem(&self) -> usize {
+        self.size
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::Device
+    }
+
+    fn set(&mut self, value: T) {
+        unsafe { memset(self, self.ptr, value, self.size, None) }.expect("memset failed");
+    }
+}
+
+impl<T: Scalar> Allocatable for ExportableDeviceMemory<T> {
+    type Shape = usize;
+    unsafe fn uninitialized(context: &Context, size: usize) -> Self {
+        assert!(size > 0, "Zero-sized malloc is forbidden");
+        let ptr = contexted_new!(context, cuMemAlloc_v2, size * std::mem::size_of::<T>())
+            .expect("Cannot allocate device memory");
+        ExportableDeviceMemory {
+            ptr,
+            size,
+            context: context.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+// Device memory is not accessible from host, and copied by pointers instead of a slice
+impl<T: Scalar> Memcpy<[T]> for ExportableDeviceMemory<T> {
+    fn copy_from(&mut self, src: &[T]) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy(self.head_addr_mut(), src.as_ptr(), self.size) }
+    }
+
+    fn copy_from_async<'a>(&'a mut self, src: &'a [T]) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy_async(self.head_addr_mut(), src.as_ptr(), self.size) }
+    }
+}
+
+impl<T: Scalar> Memcpy<ExportableDeviceMemory<T>> for [T] {
+    fn copy_from(&mut self, src: &ExportableDeviceMemory<T>) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy(self.as_mut_ptr(), src.head_addr(), src.size) }
+    }
+
+    fn copy_from_async<'a>(&'a mut self, src: &'a ExportableDeviceMemory<T>) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy_async(self.as_mut_ptr(), src.head_addr(), src.size) }
+    }
+}
+
+impl<T: Scalar> DeviceSend for &ExportableDeviceMemory<T> {
+    type Target = *const T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+impl<T: Scalar> DeviceSend for &mut ExportableDeviceMemory<T> {
+    type Target = *mut T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+impl<T: Scalar> DeviceMemory<T> {
+    /// Open a memory exported by another process
+    ///
+    /// This returns [IpcDeviceMemory] since the memory is not accessible from host.
+    /// A memory cannot be opened in the process which exports it.
+    /// `AccelError::InvalidIpcHandle` is returned if the number of elements in the handle
+    /// exceeds the size of the opened allocation.
+    ///
+    /// [IpcDeviceMemory]: ./struct.IpcDeviceMemory.html
+    pub fn open_ipc(context: ContextRef, handle: &IpcMemHandle<T>) -> Result<IpcDeviceMemory<T>> {
+        let mut raw = CUipcMemHandle_st {
+            reserved: [0; IPC_HANDLE_SIZE],
+        };
+        for (r, &byte) in raw.reserved.iter_mut().zip(handle.raw.iter()) {
+            *r = byte as _;
+        }
+        let ptr = unsafe {
+            contexted_new!(
+                &context,
+                cuIpcOpenMemHandle,
+                raw,
+                CUipcMem_flags::CU_IPC_MEM_LAZY_ENABLE_PEER_ACCESS as u32
+            )?
+        };
+        let mem = IpcDeviceMemory {
+            ptr,
+            size: handle.num_elem,
+            context,
+            phantom: PhantomData,
+        };
+        // The number of elements is decoded from bytes given by another process
+        let mut base: CUdeviceptr = 0;
+        let mut allocated: usize = 0;
+        unsafe {
+            contexted_call!(
+                &context,
+                cuMemGetAddressRange_v2,
+                &mut base,
+                &mut allocated,
+                ptr
+            )?
+        };
+        let available = (base + allocated as CUdeviceptr - ptr) as usize;
+        match handle.num_elem.checked_mul(std::mem::size_of::<T>()) {
+            Some(requested) if requested <= available => Ok(mem),
+            _ => Err(AccelError::InvalidIpcHandle {
+                reason: format!(
+                    "{} elements are requested while the allocation has {} bytes",
+                    handle.num_elem, available
+                ),
+            }),
+        }
+    }
+}
+
+/// Device memory opened from [IpcMemHandle]
+///
+/// This is not accessible from host, and it is closed when dropped.
+/// Use this in kernel arguments, or copy into/from host memories by `Memcpy`.
+///
+/// [IpcMemHandle]: ./struct.IpcMemHandle.html
+#[derive(Contexted)]
+pub struct IpcDeviceMemory<T> {
+    ptr: CUdeviceptr,
+    size: usize,
+    context: ContextRef,
+    phantom: PhantomData<T>,
+}
+
+unsafe impl<T> Sync for IpcDeviceMemory<T> {}
+unsafe impl<T> Send for IpcDeviceMemory<T> {}
+
+impl<T> Drop for IpcDeviceMemory<T> {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuIpcCloseMemHandle, self.ptr) } {
+            log::error!("Failed to close IPC memory: {:?}", e);
+        }
+    }
+}
+
+impl<T> fmt::Debug for IpcDeviceMemory<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpcDeviceMemory")
+            .field("context", &self.context)
+            .field("ptr", &self.ptr)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<T: Scalar> Memory for IpcDeviceMemory<T> {
+    type Elem = T;
+    fn head_addr(&self) -> *const T {
+        self.ptr as _
+    }
+
+    fn head_addr_mut(&mut self) -> *mut T {
+        self.ptr as _
+    }
+
+    fn num_elem(&self) -> usize {
+        self.size
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::Device
+    }
+
+    fn set(&mut self, value: T) {
+        unsafe { memset(self, self.ptr, value, self.size, None) }.expect("memset failed");
+    }
+}
+
+impl<T: Scalar> Memcpy<[T]> for IpcDeviceMemory<T> {
+    fn copy_from(&mut self, src: &[T]) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy(self.head_addr_mut(), src.as_ptr(), self.size) }
+    }
+
+    fn copy_from_async<'a>(&'a mut self, src: &'a [T]) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy_async(self.head_addr_mut(), src.as_ptr(), self.size) }
+    }
+}
+
+impl<T: Scalar> Memcpy<IpcDeviceMemory<T>> for [T] {
+    fn copy_from(&mut self, src: &IpcDeviceMemory<T>) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy(self.as_mut_ptr(), src.head_addr(), src.size) }
+    }
+
+    fn copy_from_async<'a>(&'a mut self, src: &'a IpcDeviceMemory<T>) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        unsafe { memcpy_async(self.as_mut_ptr(), src.head_addr(), src.size) }
+    }
+}
+
+impl<T: Scalar> DeviceSend for &IpcDeviceMemory<T> {
+    type Target = *const T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+impl<T: Scalar> DeviceSend for &mut IpcDeviceMemory<T> {
+    type Target = *mut T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_layout() {
+        assert_eq!(std::mem::size_of::<CUipcMemHandle_st>(), IPC_HANDLE_SIZE);
+        assert_eq!(std::mem::size_of::<CUipcEventHandle_st>(), IPC_HANDLE_SIZE);
+    }
+
+    #[test]
+    fn encode() {
+        let mut raw = [0_u8; IPC_HANDLE_SIZE];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let handle = IpcMemHandle::<u16>::from_raw(raw, 0x0102_0304);
+        let bytes = handle.to_bytes();
+        assert_eq!(bytes.len(), IpcMemHandle::<u16>::ENCODED_SIZE);
+        assert_eq!(&bytes[..IPC_HANDLE_SIZE], &raw[..]);
+        assert_eq!(&bytes[64..72], &[4, 3, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(&bytes[72..], &[2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decode() {
+        let handle = IpcMemHandle::<f64>::from_raw([0xff; IPC_HANDLE_SIZE], 1 << 40);
+        let bytes = handle.to_bytes();
+        let decoded = IpcMemHandle::<f64>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, handle);
+        assert_eq!(decoded.num_elem(), 1 << 40);
+        // same element size is accepted
+        assert!(IpcMemHandle::<u64>::from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let bytes = IpcMemHandle::<f32>::from_raw([0; IPC_HANDLE_SIZE], 12).to_bytes();
+        // (input, element size)
+        let table: [(&[u8], usize); 4] = [
+            (&bytes[..75], 4),
+            (&[], 4),
+            (&[bytes.clone(), vec![0]].concat(), 4),
+            (&bytes, 8),
+        ];
+        for &(input, elem_size) in &table {
+            let result = match elem_size {
+                4 => IpcMemHandle::<u32>::from_bytes(input).map(|_| ()),
+                _ => IpcMemHandle::<u64>::from_bytes(input).map(|_| ()),
+            };
+            match result {
+                Err(AccelError::InvalidIpcHandle { .. }) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn export_open() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let mem = ExportableDeviceMemory::<u32>::from_elem(&context, 12, 3);
+        let handle = mem.ipc_handle()?;
+        assert_eq!(handle.num_elem(), 12);
+
+        // A memory cannot be opened in the process which exports it
+        let hex: String = handle
+            .to_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(&["--exact", "memory::ipc::tests::open_in_child"])
+            .env(CHILD_HANDLE, hex)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut host = vec![0_u32; 12];
+        host.copy_from(&mem);
+        assert_eq!(host, vec![4; 12]);
+
+        // managed memory cannot be shared by CUDA IPC
+        let managed = DeviceMemory::<u32>::zeros(&context, 12);
+        let ptr = managed.head_addr() as CUdeviceptr;
+        assert!(unsafe { contexted_new!(&managed, cuIpcGetMemHandle, ptr) }.is_err());
+        Ok(())
+    }
+
+    /// Environment variable to pass a handle into `open_in_child` in hex
+    const CHILD_HANDLE: &str = "ACCEL_TEST_IPC_MEM_HANDLE";
+
+    /// Consumer run in a child process by `export_open`, and do nothing in usual test run
+    #[test]
+    fn open_in_child() -> Result<()> {
+        let hex = match std::env::var(CHILD_HANDLE) {
+            Ok(hex) => hex,
+            Err(_) => return Ok(()),
+        };
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        let handle = IpcMemHandle::<u32>::from_bytes(&bytes)?;
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let mut mem = DeviceMemory::open_ipc(context.get_ref(), &handle)?;
+        let mut host = vec![0_u32; handle.num_elem()];
+        host.copy_from(&mem);
+        assert_eq!(host, vec![3; 12]);
+        mem.set(4);
+        drop(mem);
+
+        // The size in the handle is checked with the opened allocation
+        let larger = IpcMemHandle::<u32>::from_raw(handle.raw(), handle.num_elem() + 1);
+        match DeviceMemory::open_ipc(context.get_ref(), &larger) {
+            Err(AccelError::InvalidIpcHandle { .. }) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        Ok(())
+    }
+}
@@ -15,21 +15,27 @@
 //! | [RegisteredMemory]  | Host         | ✓         |  ✓          |  ✓       | A host memory registered into CUDA memory management system            |
 //! | [PageLockedMemory]  | Host         | ✓         |  ✓          |  ✓       | OS memory paging is disabled for accelerating memory transfer          |
 //! | [DeviceMemory]      | Device       | ✓         |  ✓          |  ✓       | allocated on device as a single span                                   |
+//! | [AsyncDeviceMemory] | Device       | -         |  ✓          |  -       | allocated and freed on device in stream order                          |
+//! | [ExportableDeviceMemory] | Device  | -         |  ✓          |  -       | allocated on device, and exportable to other processes                 |
+//! | [IpcDeviceMemory]   | Device       | -         |  ✓          |  -       | device memory exported by another process                              |
 //! | [Array]             | Device       | ✓         |  ✓          |  -       | properly aligned memory on device for using Texture and Surface memory |
 //!
 //! Traits
 //! -------
 //!
-//! |traits       |`[T]`|[RegisteredMemory]|[PageLockedMemory]|[DeviceMemory]|[Array]| Description                                |
-//! |:------------|:---:|:----------------:|:----------------:|:------------:|:-----:|:-------------------------------------------|
-//! |[Memory]     | ✓   | ✓                | ✓                | ✓            | ✓     | Has Unified address and element size       |
-//! |[Contexted]  | -   | ✓                | ✓                | ✓            | ✓     | with CUDA Context                          |
-//! |[Continuous] | ✓   | ✓                | ✓                | ✓            | -     | Can be treated as a Rust slice             |
-//! |[Allocatable]| -   | -                | ✓                | ✓            | ✓     | Newly allocatable with its shape and value |
+//! |traits       |`[T]`|[RegisteredMemory]|[PageLockedMemory]|[DeviceMemory]|[AsyncDeviceMemory]|[Array]| Description                                |
+//! |:------------|:---:|:----------------:|:----------------:|:------------:|:-----------------:|:-----:|:-------------------------------------------|
+//! |[Memory]     | ✓   | ✓                | ✓                | ✓            | ✓                 | ✓     | Has Unified address and element size       |
+//! |[Contexted]  | -   | ✓                | ✓                | ✓            | ✓                 | ✓     | with CUDA Context                          |
+//! |[Continuous] | ✓   | ✓                | ✓                | ✓            | -                 | -     | Can be treated as a Rust slice             |
+//! |[Allocatable]| -   | -                | ✓                | ✓            | -                 | ✓     | Newly allocatable with its shape and value |
 //!
 //! [RegisteredMemory]: ./struct.RegisteredMemory.html
 //! [PageLockedMemory]: ./struct.PageLockedMemory.html
 //! [DeviceMemory]: ./struct.DeviceMemory.html
+//! [AsyncDeviceMemory]: ./struct.AsyncDeviceMemory.html
+//! [ExportableDeviceMemory]: ./struct.ExportableDeviceMemory.html
+//! [IpcDeviceMemory]: ./struct.IpcDeviceMemory.html
 //! [Array]: ./struct.Array.html
 //!
 //! [Memory]: ./trait.Memory.html
@@ -42,7 +48,9 @@ mod array;
 mod device;
 mod dimension;
 mod info;
+mod ipc;
 mod page_locked;
+mod pool;
 mod registered;
 mod scalar;
 mod slice;
@@ -51,7 +59,9 @@ pub use array::*;
 pub use device::*;
 pub use dimension::*;
 pub use info::*;
+pub use ipc::*;
 pub use page_locked::*;
+pub use pool::*;
 pub use registered::*;
 pub use scalar::*;
 
@@ -282,22 +292,21 @@ pub trait Memcpy<Target: Memory<Elem = Self::Elem> + ?Sized>: Memory {
     /// ```
     /// use accel::*;
     ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///   let device = Device::nth(0).unwrap();
-    ///   let ctx = device.create_context();
-    ///   let mut dest = DeviceMemory::<f32>::zeros(&ctx, 12);
-    ///   let src = PageLockedMemory::<f32>::zeros(&ctx, 12);
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// let mut dest = DeviceMemory::<f32>::zeros(&ctx, 12);
+    /// let src = PageLockedMemory::<f32>::zeros(&ctx, 12);
+    /// // The future works with any executor
+    /// futures::executor::block_on(async {
     ///   dest.copy_from_async(&src).await;
-    /// }
+    /// });
     /// ```
     ///
     /// - Arrays are captured until await:
     ///
     /// ```
     /// # use accel::*;
-    /// # #[tokio::main]
-    /// # async fn main() {
+    /// # futures::executor::block_on(async {
     /// # let device = Device::nth(0).unwrap();
     /// # let ctx = device.create_context();
     /// # let mut dest = DeviceMemory::<f32>::zeros(&ctx, 12);
@@ -305,13 +314,12 @@ pub trait Memcpy<Target: Memory<Elem = Self::Elem> + ?Sized>: Memory {
     /// let future = dest.copy_from_async(&src);
     /// println!("src[0] = {}", src[0]);  // Source is always accessible as usual &-reference
     /// future.await;
-    /// # }
+    /// # });
     /// ```
     ///
     /// ```compile_fail
     /// # use accel::*;
-    /// # #[tokio::main]
-    /// # async fn main() {
+    /// # futures::executor::block_on(async {
     /// # let device = Device::nth(0).unwrap();
     /// # let ctx = device.create_context();
     /// # let mut dest = DeviceMemory::<f32>::zeros(&ctx, 12);
@@ -319,7 +327,7 @@ pub trait Memcpy<Target: Memory<Elem = Self::Elem> + ?Sized>: Memory {
     /// let future = dest.copy_from_async(&src);
     /// println!("dest[0] = {}", dest[0]);  // Destination is not accessible until .await
     /// future.await;
-    /// # }
+    /// # });
     /// ```
     fn copy_from_async<'a>(&'a mut self, src: &'a Target) -> BoxFuture<'a, ()>;
 }
@@ -0,0 +1,544 @@
+//! [Stream-ordered memory allocator] and memory pools
+//!
+//! Allocation and deallocation are issued into a stream as other async operations,
+//! and memories freed in a stream are reused by later allocations without synchronization.
+//! This requires CUDA 11.2 or later.
+//!
+//! [Stream-ordered memory allocator]: https://docs.nvidia.com/cuda/cuda-driver-api/group__CUDA__MALLOC__ASYNC.html
+
+use super::*;
+use crate::{error::*, *};
+use cuda::*;
+use futures::future::BoxFuture;
+use std::{fmt, marker::PhantomData, mem::ManuallyDrop, os::raw::c_void};
+
+/// Driver version introducing stream-ordered allocator
+const REQUIRED_DRIVER: DriverVersion = DriverVersion {
+    major: 11,
+    minor: 2,
+};
+
+/// Bindings of CUDA 11.2 APIs missing in cuda-driver-sys
+///
+/// They are resolved from loaded libcuda at runtime instead of linking,
+/// since accel also works with older drivers without them.
+/// All signatures are listed in one table `Api`, which is resolved only once in the process.
+/// The loader is only implemented on Linux, and they return `CUDA_ERROR_NOT_SUPPORTED` on other platforms.
+#[allow(non_snake_case, non_camel_case_types)]
+mod ffi {
+    use super::REQUIRED_DRIVER;
+    use crate::{driver::*, error::*};
+    use cuda::*;
+    use std::os::raw::c_void;
+
+    pub type CUmemoryPool = *mut c_void;
+
+    #[repr(C)]
+    pub struct CUmemPoolProps {
+        pub allocType: CUmemAllocationType,
+        /// `CUmemAllocationHandleType`, where `0` means `CU_MEM_HANDLE_TYPE_NONE`
+        pub handleTypes: u32,
+        pub location: CUmemLocation,
+        pub win32SecurityAttributes: *mut c_void,
+        pub reserved: [u8; 64],
+    }
+
+    /// `CUmemPool_attribute`
+    pub const CU_MEMPOOL_ATTR_RELEASE_THRESHOLD: u32 = 4;
+    pub const CU_MEMPOOL_ATTR_RESERVED_MEM_CURRENT: u32 = 5;
+    pub const CU_MEMPOOL_ATTR_USED_MEM_CURRENT: u32 = 7;
+
+    /// Address of a symbol in libraries already loaded, i.e. libcuda
+    #[cfg(target_os = "linux")]
+    pub(super) fn lookup(name: &str) -> Option<usize> {
+        use std::{ffi::CString, os::raw::c_char};
+
+        #[link(name = "dl")]
+        extern "C" {
+            fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        }
+
+        let name = CString::new(name).ok()?;
+        // `RTLD_DEFAULT` is null on Linux
+        let addr = unsafe { dlsym(std::ptr::null_mut(), name.as_ptr()) };
+        if addr.is_null() {
+            None
+        } else {
+            Some(addr as usize)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub(super) fn lookup(_name: &str) -> Option<usize> {
+        None
+    }
+
+    macro_rules! api_table {
+        ($(pub fn $name:ident($($arg:ident: $ty:ty),*);)*) => {
+            /// Typed entry points, whose signatures follow cuda.h of CUDA 11.2
+            pub(super) struct Api {
+                $(pub $name: unsafe extern "C" fn($($ty),*) -> CUresult,)*
+            }
+
+            impl Api {
+                /// Resolve all entry points, or get the name of the first missing one
+                pub(super) fn load<F>(lookup: F) -> std::result::Result<Self, &'static str>
+                where
+                    F: Fn(&str) -> Option<usize>,
+                {
+                    Ok(Api {
+                        $($name: {
+                            let addr = lookup(stringify!($name)).ok_or(stringify!($name))?;
+                            // Safety: the symbol of this name has this signature in CUDA 11.2 or later
+                            unsafe { std::mem::transmute::<usize, unsafe extern "C" fn($($ty),*) -> CUresult>(addr) }
+                        },)*
+                    })
+                }
+            }
+
+            $(
+                pub unsafe fn $name($($arg: $ty),*) -> CUresult {
+                    match &*API {
+                        Ok(api) => (api.$name)($($arg),*),
+                        Err(_) => CUresult::CUDA_ERROR_NOT_SUPPORTED,
+                    }
+                }
+            )*
+        };
+    }
+
+    api_table! {
+        pub fn cuMemAllocAsync(dptr: *mut CUdeviceptr, bytesize: usize, hStream: CUstream);
+        pub fn cuMemAllocFromPoolAsync(
+            dptr: *mut CUdeviceptr,
+            bytesize: usize,
+            pool: CUmemoryPool,
+            hStream: CUstream
+        );
+        pub fn cuMemFreeAsync(dptr: CUdeviceptr, hStream: CUstream);
+        pub fn cuDeviceGetDefaultMemPool(pool_out: *mut CUmemoryPool, dev: CUdevice);
+        pub fn cuMemPoolCreate(pool: *mut CUmemoryPool, poolProps: *const CUmemPoolProps);
+        pub fn cuMemPoolDestroy(pool: CUmemoryPool);
+        pub fn cuMemPoolSetAttribute(pool: CUmemoryPool, attr: u32, value: *mut c_void);
+        pub fn cuMemPoolGetAttribute(pool: CUmemoryPool, attr: u32, value: *mut c_void);
+        pub fn cuMemPoolTrimTo(pool: CUmemoryPool, minBytesToKeep: usize);
+    }
+
+    lazy_static::lazy_static! {
+        /// Installed driver, which never changes while the process is running
+        static ref INSTALLED: Option<DriverVersion> = driver_version().ok();
+        static ref API: std::result::Result<Api, &'static str> = Api::load(lookup);
+    }
+
+    /// Check the driver supports these APIs without querying it every time
+    pub(super) fn require() -> Result<()> {
+        match *INSTALLED {
+            Some(installed) if installed >= REQUIRED_DRIVER => {}
+            // query again to get a descriptive error
+            _ => require_driver(REQUIRED_DRIVER)?,
+        }
+        match &*API {
+            Ok(_) => Ok(()),
+            Err(name) => Err(AccelError::CUDAError {
+                api_name: (*name).into(),
+                error: CUresult::CUDA_ERROR_NOT_FOUND,
+            }),
+        }
+    }
+}
+
+/// Memory pool of stream-ordered allocator on a device
+///
+/// ```no_run
+/// # use accel::*;
+/// let device = Device::nth(0).unwrap();
+/// let ctx = device.create_context();
+/// let stream = Stream::new(ctx.get_ref());
+/// let pool = MemPool::new(&device).unwrap();
+/// // Keep freed memories in the pool up to 1GB instead of returning them to OS
+/// pool.set_release_threshold(1 << 30).unwrap();
+/// let mem = pool.alloc_async::<f32>(&stream, 1024).unwrap();
+/// drop(mem); // freed in stream order
+/// stream.sync().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MemPool {
+    pool: ffi::CUmemoryPool,
+    /// Only pools created by `MemPool::new` are destroyed
+    owned: bool,
+}
+
+unsafe impl Sync for MemPool {}
+unsafe impl Send for MemPool {}
+
+impl Drop for MemPool {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+        // Resources are released after all allocations are freed
+        if let Err(e) = unsafe { ffi_call!(ffi::cuMemPoolDestroy, self.pool) } {
+            log::error!("Failed to destroy memory pool: {:?}", e);
+        }
+    }
+}
+
+impl MemPool {
+    /// Create a new memory pool on the device
+    pub fn new(device: &Device) -> Result<Self> {
+        ffi::require()?;
+        let props = ffi::CUmemPoolProps {
+            allocType: CUmemAllocationType::CU_MEM_ALLOCATION_TYPE_PINNED,
+            handleTypes: 0,
+            location: CUmemLocation {
+                type_: CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE,
+                id: device.device,
+            },
+            win32SecurityAttributes: std::ptr::null_mut(),
+            reserved: [0; 64],
+        };
+        let pool = unsafe { ffi_new!(ffi::cuMemPoolCreate, &props)? };
+        Ok(MemPool { pool, owned: true })
+    }
+
+    /// The default memory pool of the device used by `DeviceMemory::alloc_async`
+    pub fn default_of(device: &Device) -> Result<Self> {
+        ffi::require()?;
+        let pool = unsafe { ffi_new!(ffi::cuDeviceGetDefaultMemPool, device.device)? };
+        Ok(MemPool { pool, owned: false })
+    }
+
+    fn get_attribute(&self, attr: u32) -> Result<u64> {
+        let mut value = 0_u64;
+        unsafe {
+            ffi_call!(
+                ffi::cuMemPoolGetAttribute,
+                self.pool,
+                attr,
+                &mut value as *mut u64 as *mut c_void
+            )?;
+        }
+        Ok(value)
+    }
+
+    /// Set the amount of reserved memory in bytes kept in the pool when synchronized
+    ///
+    /// The pool releases unused memories to OS at synchronization by default (threshold is zero).
+    /// Set large value, e.g. `u64::MAX`, to avoid reallocation in iterations.
+    pub fn set_release_threshold(&self, bytes: u64) -> Result<()> {
+        let mut value = bytes;
+        unsafe {
+            ffi_call!(
+                ffi::cuMemPoolSetAttribute,
+                self.pool,
+                ffi::CU_MEMPOOL_ATTR_RELEASE_THRESHOLD,
+                &mut value as *mut u64 as *mut c_void
+            )
+        }
+    }
+
+    pub fn release_threshold(&self) -> Result<u64> {
+        self.get_attribute(ffi::CU_MEMPOOL_ATTR_RELEASE_THRESHOLD)
+    }
+
+    /// Memory in bytes currently reserved by the pool from OS
+    pub fn reserved_memory(&self) -> Result<u64> {
+        self.get_attribute(ffi::CU_MEMPOOL_ATTR_RESERVED_MEM_CURRENT)
+    }
+
+    /// Memory in bytes currently allocated from the pool
+    pub fn used_memory(&self) -> Result<u64> {
+        self.get_attribute(ffi::CU_MEMPOOL_ATTR_USED_MEM_CURRENT)
+    }
+
+    /// Release unused memories until the reserved memory becomes `min_bytes_to_keep`
+    pub fn trim_to(&self, min_bytes_to_keep: usize) -> Result<()> {
+        unsafe { ffi_call!(ffi::cuMemPoolTrimTo, self.pool, min_bytes_to_keep) }
+    }
+
+    /// Allocate memory from this pool in the order of `stream`
+    pub fn alloc_async<'stream, T: Scalar>(
+        &self,
+        stream: &'stream Stream,
+        n: usize,
+    ) -> Result<AsyncDeviceMemory<'stream, T>> {
+        ffi::require()?;
+        assert!(n > 0, "Zero-sized malloc is forbidden");
+        let ptr = unsafe {
+            contexted_new!(
+                stream,
+                ffi::cuMemAllocFromPoolAsync,
+                n * T::size_of(),
+                self.pool,
+                stream.stream
+            )?
+        };
+        Ok(AsyncDeviceMemory::new(ptr, n, stream))
+    }
+}
+
+/// Memory allocated on the device in stream order
+///
+/// Different from [DeviceMemory], this is not accessible from host,
+/// and it is freed in the order of the stream used in allocation when dropped.
+/// Use this in kernel arguments, or copy into/from host memories by `Memcpy`.
+///
+/// [DeviceMemory]: ./struct.DeviceMemory.html
+#[derive(Contexted)]
+pub struct AsyncDeviceMemory<'stream, T> {
+    ptr: CUdeviceptr,
+    size: usize,
+    stream: &'stream Stream,
+    context: ContextRef,
+    phantom: PhantomData<T>,
+}
+
+unsafe impl<T> Sync for AsyncDeviceMemory<'_, T> {}
+unsafe impl<T> Send for AsyncDeviceMemory<'_, T> {}
+
+impl<T> Drop for AsyncDeviceMemory<'_, T> {
+    fn drop(&mut self) {
+        if let Err(e) =
+            unsafe { contexted_call!(self, ffi::cuMemFreeAsync, self.ptr, self.stream.stream) }
+        {
+            log::error!("Failed to free device memory in stream order: {:?}", e);
+        }
+    }
+}
+
+impl<T> fmt::Debug for AsyncDeviceMemory<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncDeviceMemory")
+            .field("context", &self.context)
+            .field("ptr", &self.ptr)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl<'stream, T> AsyncDeviceMemory<'stream, T> {
+    fn new(ptr: CUdeviceptr, size: usize, stream: &'stream Stream) -> Self {
+        AsyncDeviceMemory {
+            ptr,
+            size,
+            stream,
+            context: stream.get_ref(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Stream where this memory is allocated and freed
+    pub fn stream(&self) -> &'stream Stream {
+        self.stream
+    }
+
+    /// Free in the order of another stream instead of the stream used in allocation
+    pub fn free_on(self, stream: &Stream) -> Result<()> {
+        let this = ManuallyDrop::new(self);
+        unsafe { contexted_call!(&*this, ffi::cuMemFreeAsync, this.ptr, stream.stream) }
+    }
+}
+
+impl<T: Scalar> DeviceMemory<T> {
+    /// Allocate memory from the default memory pool of the device in the order of `stream`
+    ///
+    /// This returns [AsyncDeviceMemory] since the memory is not accessible from host.
+    /// Requires CUDA 11.2 or later, and returns `AccelError::DriverTooOld` otherwise.
+    ///
+    /// ```no_run
+    /// # use accel::*;
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// let stream = Stream::new(ctx.get_ref());
+    /// let mut mem = DeviceMemory::<i32>::alloc_async(&stream, 12).unwrap();
+    /// mem.copy_from(&[1; 12]);
+    /// let mut host = vec![0; 12];
+    /// host.copy_from(&mem);
+    /// assert_eq!(host, vec![1; 12]);
+    /// ```
+    ///
+    /// [AsyncDeviceMemory]: ./struct.AsyncDeviceMemory.html
+    pub fn alloc_async(stream: &Stream, n: usize) -> Result<AsyncDeviceMemory<'_, T>> {
+        ffi::require()?;
+        assert!(n > 0, "Zero-sized malloc is forbidden");
+        let ptr = unsafe {
+            contexted_new!(
+                stream,
+                ffi::cuMemAllocAsync,
+                n * T::size_of(),
+                stream.stream
+            )?
+        };
+        Ok(AsyncDeviceMemory::new(ptr, n, stream))
+    }
+}
+
+impl<T: Scalar> Memory for AsyncDeviceMemory<'_, T> {
+    type Elem = T;
+    fn head_addr(&self) -> *const T {
+        self.ptr as _
+    }
+
+    fn head_addr_mut(&mut self) -> *mut T {
+        self.ptr as _
+    }
+
+    fn num_elem(&self) -> usize {
+        self.size
+    }
+
+    fn memory_type(&self) -> MemoryType {
+        MemoryType::Device
+    }
+
+    fn set(&mut self, value: T) {
+        unsafe { memset(self, self.ptr, value, self.size, Some(self.stream.stream)) }
+            .and_then(|_| self.stream.sync())
+            .expect("memset failed");
+    }
+}
+
+/// Issue memcpy into the stream where the device memory is allocated
+fn memcpy_in_order<T: Scalar>(stream: &Stream, dst: *mut T, src: *const T, n: usize) -> Result<()> {
+    unsafe {
+        contexted_call!(
+            stream,
+            cuMemcpyAsync,
+            dst as CUdeviceptr,
+            src as CUdeviceptr,
+            n * T::size_of(),
+            stream.stream
+        )
+    }
+}
+
+fn wait<'a>(stream: &'a Stream) -> BoxFuture<'a, ()> {
+    Box::pin(async move {
+        stream::StreamFuture::new(stream)
+            .await
+            .expect("async memcpy failed")
+    })
+}
+
+impl<T: Scalar> Memcpy<[T]> for AsyncDeviceMemory<'_, T> {
+    fn copy_from(&mut self, src: &[T]) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        memcpy_in_order(self.stream, self.head_addr_mut(), src.as_ptr(), self.size)
+            .and_then(|_| self.stream.sync())
+            .expect("memcpy into stream-ordered memory failed");
+    }
+
+    fn copy_from_async<'a>(&'a mut self, src: &'a [T]) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        memcpy_in_order(self.stream, self.head_addr_mut(), src.as_ptr(), self.size)
+            .expect("memcpy into stream-ordered memory failed");
+        wait(self.stream)
+    }
+}
+
+impl<'stream, T: Scalar> Memcpy<AsyncDeviceMemory<'stream, T>> for [T] {
+    fn copy_from(&mut self, src: &AsyncDeviceMemory<'stream, T>) {
+        assert_eq!(self.num_elem(), src.num_elem());
+        memcpy_in_order(src.stream, self.as_mut_ptr(), src.head_addr(), src.size)
+            .and_then(|_| src.stream.sync())
+            .expect("memcpy from stream-ordered memory failed");
+    }
+
+    fn copy_from_async<'a>(
+        &'a mut self,
+        src: &'a AsyncDeviceMemory<'stream, T>,
+    ) -> BoxFuture<'a, ()> {
+        assert_eq!(self.num_elem(), src.num_elem());
+        memcpy_in_order(src.stream, self.as_mut_ptr(), src.head_addr(), src.size)
+            .expect("memcpy from stream-ordered memory failed");
+        wait(src.stream)
+    }
+}
+
+impl<T: Scalar> DeviceSend for &AsyncDeviceMemory<'_, T> {
+    type Target = *const T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+impl<T: Scalar> DeviceSend for &mut AsyncDeviceMemory<'_, T> {
+    type Target = *mut T;
+    fn as_kernel_parameter(&self) -> *mut c_void {
+        &self.ptr as *const CUdeviceptr as *mut c_void
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_props_layout() {
+        // sizeof(CUmemPoolProps) in cuda.h of CUDA 11.2
+        assert_eq!(std::mem::size_of::<ffi::CUmemPoolProps>(), 88);
+        assert_eq!(std::mem::align_of::<ffi::CUmemPoolProps>(), 8);
+    }
+
+    #[test]
+    fn missing_symbol() {
+        assert_eq!(ffi::lookup("cuNeverExistingFunction"), None);
+    }
+
+    #[test]
+    fn load_api() {
+        // Fake addresses, which are never called
+        assert!(ffi::Api::load(|_| Some(1)).is_ok());
+        assert_eq!(
+            ffi::Api::load(|name| if name == "cuMemPoolTrimTo" {
+                None
+            } else {
+                Some(1)
+            })
+            .err(),
+            Some("cuMemPoolTrimTo")
+        );
+        assert_eq!(ffi::Api::load(|_| None).err(), Some("cuMemAllocAsync"));
+    }
+
+    #[test]
+    fn alloc_free() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let stream = Stream::new(ctx.get_ref());
+        let mut mem = match DeviceMemory::<u32>::alloc_async(&stream, 12) {
+            Err(AccelError::DriverTooOld { .. }) => return Ok(()),
+            result => result?,
+        };
+        mem.set(3);
+        let mut host = vec![0_u32; 12];
+        host.copy_from(&mem);
+        assert_eq!(host, vec![3; 12]);
+        mem.copy_from(&[1; 12]);
+        futures::executor::block_on(host.copy_from_async(&mem));
+        assert_eq!(host, vec![1; 12]);
+        drop(mem);
+        stream.sync()?;
+        Ok(())
+    }
+
+    #[test]
+    fn mem_pool() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let stream = Stream::new(ctx.get_ref());
+        let pool = match MemPool::new(&device) {
+            Err(AccelError::DriverTooOld { .. }) => return Ok(()),
+            result => result?,
+        };
+        pool.set_release_threshold(1 << 20)?;
+        assert_eq!(pool.release_threshold()?, 1 << 20);
+        let mem = pool.alloc_async::<f32>(&stream, 1024)?;
+        assert!(pool.used_memory()? >= 4096);
+        mem.free_on(&stream)?;
+        stream.sync()?;
+        pool.trim_to(0)?;
+        let _default = MemPool::default_of(&device)?;
+        Ok(())
+    }
+}
@@ -64,55 +64,122 @@ impl<T: Scalar> Memory for [T] {
     }
 }
 
+/// Contexts of device memories in different contexts, which requires peer-to-peer memcpy
+fn peer_contexts<T>(dst: *const T, src: *const T) -> Option<(ContextRef, ContextRef)> {
+    if memory_type(dst) != MemoryType::Device || memory_type(src) != MemoryType::Device {
+        return None;
+    }
+    let dst_ctx = get_context(dst)?;
+    let src_ctx = get_context(src)?;
+    if dst_ctx == src_ctx {
+        return None;
+    }
+    Some((dst_ctx, src_ctx))
+}
+
+/// Copy `n` elements between any types of memories by pointers
+///
+/// This is used for memories which cannot be viewed as a slice, i.e. not accessible from host.
+///
+/// Safety
+/// ------
+/// - `dst` and `src` must be valid for `n` elements, and must not overlap
+pub(super) unsafe fn memcpy<T: Scalar>(dst: *mut T, src: *const T, n: usize) {
+    if let Some((dst_ctx, src_ctx)) = peer_contexts(dst, src) {
+        contexted_call!(
+            &dst_ctx,
+            cuMemcpyPeer,
+            dst as CUdeviceptr,
+            dst_ctx.ptr,
+            src as CUdeviceptr,
+            src_ctx.ptr,
+            n * T::size_of()
+        )
+        .expect("Peer-to-peer memcpy failed")
+    } else if let Some(ctx) = get_context(dst).or_else(|| get_context(src)) {
+        contexted_call!(
+            &ctx,
+            cuMemcpy,
+            dst as CUdeviceptr,
+            src as CUdeviceptr,
+            n * T::size_of()
+        )
+        .unwrap()
+    } else {
+        std::ptr::copy_nonoverlapping(src, dst, n);
+    }
+}
+
+/// Start copying `n` elements between any types of memories by pointers
+///
+/// Safety
+/// ------
+/// - `dst` and `src` must be valid for `n` elements until the returned future completes,
+///   and must not overlap
+pub(super) unsafe fn memcpy_async<'a, T: Scalar>(
+    dst: *mut T,
+    src: *const T,
+    n: usize,
+) -> BoxFuture<'a, ()> {
+    let byte_count = n * T::size_of();
+    if let Some((dst_ctx, src_ctx)) = peer_contexts(dst, src) {
+        let stream = stream::StreamPool::for_context(dst_ctx)
+            .checkout()
+            .expect("Failed to get a stream from pool");
+        contexted_call!(
+            &dst_ctx,
+            cuMemcpyPeerAsync,
+            dst as CUdeviceptr,
+            dst_ctx.ptr,
+            src as CUdeviceptr,
+            src_ctx.ptr,
+            byte_count,
+            stream.stream
+        )
+        .expect("Failed to start async peer-to-peer memcpy");
+        return Box::pin(async {
+            stream
+                .into_future()
+                .await
+                .expect("Async memcpy thread failed")
+        });
+    }
+    if let Some(ctx) = get_context(dst).or_else(|| get_context(src)) {
+        let stream = stream::StreamPool::for_context(ctx)
+            .checkout()
+            .expect("Failed to get a stream from pool");
+        contexted_call!(
+            &ctx,
+            cuMemcpyAsync,
+            dst as CUdeviceptr,
+            src as CUdeviceptr,
+            byte_count,
+            stream.stream
+        )
+        .expect("Failed to start async memcpy");
+        Box::pin(async {
+            stream
+                .into_future()
+                .await
+                .expect("Async memcpy thread failed")
+        })
+    } else {
+        std::ptr::copy_nonoverlapping(src, dst, n);
+        Box::pin(async {})
+    }
+}
+
 impl<T: Scalar> Memcpy<[T]> for [T] {
     fn copy_from(&mut self, src: &[T]) {
         assert_ne!(self.head_addr(), src.head_addr());
         assert_eq!(self.num_elem(), src.num_elem());
-        if let Some(ctx) = get_context(self.head_addr()).or_else(|| get_context(src.head_addr())) {
-            unsafe {
-                contexted_call!(
-                    &ctx,
-                    cuMemcpy,
-                    self.head_addr_mut() as CUdeviceptr,
-                    src.as_ptr() as CUdeviceptr,
-                    self.num_elem() * T::size_of()
-                )
-            }
-            .unwrap()
-        } else {
-            self.copy_from_slice(src);
-        }
+        unsafe { memcpy(self.as_mut_ptr(), src.as_ptr(), self.len()) }
     }
 
     fn copy_from_async<'a>(&'a mut self, src: &'a [T]) -> BoxFuture<'a, ()> {
         assert_ne!(self.head_addr(), src.head_addr());
         assert_eq!(self.num_elem(), src.num_elem());
-        let ctx1 = get_context(self.head_addr());
-        let ctx2 = get_context(src.head_addr());
-        if let Some(ctx) = ctx1.or(ctx2) {
-            let stream = stream::Stream::new(ctx);
-            let byte_count = self.len() * std::mem::size_of::<T>();
-            unsafe {
-                contexted_call!(
-                    &ctx,
-                    cuMemcpyAsync,
-                    src.as_ptr() as CUdeviceptr,
-                    self.as_mut_ptr() as CUdeviceptr,
-                    byte_count,
-                    stream.stream
-                )
-            }
-            .expect("Failed to start async memcpy");
-            Box::pin(async {
-                stream
-                    .into_future()
-                    .await
-                    .expect("Async memcpy thread failed")
-            })
-        } else {
-            self.copy_from_slice(src);
-            Box::pin(async {})
-        }
+        unsafe { memcpy_async(self.as_mut_ptr(), src.as_ptr(), self.len()) }
     }
 }
 
@@ -178,6 +245,7 @@ impl<T: Scalar> Continuous for [T] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::executor::block_on;
 
     #[test]
     fn memory_type_host_vec() -> error::Result<()> {
@@ -207,8 +275,8 @@ mod tests {
         Ok(())
     }
 
-    #[tokio::test]
-    async fn memcpy_async_host() {
+    #[test]
+    fn memcpy_async_host() {
         let a = vec![1_u32; 12];
         let mut b1 = vec![0_u32; 12];
         let mut b2 = vec![0_u32; 12];
@@ -216,16 +284,18 @@ mod tests {
         let fut1 = b1.copy_from_async(a.as_slice());
         let fut2 = b2.copy_from_async(a.as_slice());
         let fut3 = b3.copy_from_async(a.as_slice());
-        fut3.await;
-        fut2.await;
-        fut1.await;
+        block_on(async {
+            fut3.await;
+            fut2.await;
+            fut1.await;
+        });
         assert_eq!(a, b1);
         assert_eq!(a, b2);
         assert_eq!(a, b3);
     }
 
-    #[tokio::test]
-    async fn memcpy_async_d2h() {
+    #[test]
+    fn memcpy_async_d2h() {
         let device = Device::nth(0).unwrap();
         let ctx = device.create_context();
         let a = DeviceMemory::from_elem(&ctx, 12, 1_u32);
@@ -235,16 +305,18 @@ mod tests {
         let fut1 = b1.copy_from_async(&a);
         let fut2 = b2.copy_from_async(&a);
         let fut3 = b3.copy_from_async(&a);
-        fut3.await;
-        fut2.await;
-        fut1.await;
+        block_on(async {
+            fut3.await;
+            fut2.await;
+            fut1.await;
+        });
         assert_eq!(a.as_slice(), b1.as_slice());
         assert_eq!(a.as_slice(), b2.as_slice());
         assert_eq!(a.as_slice(), b3.as_slice());
     }
 
-    #[tokio::test]
-    async fn memcpy_async_h2d() {
+    #[test]
+    fn memcpy_async_h2d() {
         let device = Device::nth(0).unwrap();
         let ctx = device.create_context();
         let a = PageLockedMemory::from_elem(&ctx, 12, 1_u32);
@@ -254,16 +326,54 @@ mod tests {
         let fut1 = b1.copy_from_async(&a);
         let fut2 = b2.copy_from_async(&a);
         let fut3 = b3.copy_from_async(&a);
-        fut3.await;
-        fut2.await;
-        fut1.await;
+        block_on(async {
+            fut3.await;
+            fut2.await;
+            fut1.await;
+        });
         assert_eq!(a.as_slice(), b1.as_slice());
         assert_eq!(a.as_slice(), b2.as_slice());
         assert_eq!(a.as_slice(), b3.as_slice());
     }
 
-    #[tokio::test]
-    async fn memcpy_async_d2d() {
+    #[test]
+    fn memcpy_async_direction() {
+        let device = Device::nth(0).unwrap();
+        let ctx = device.create_context();
+        let a = DeviceMemory::from_elem(&ctx, 12, 1_u32);
+        let mut b = DeviceMemory::from_elem(&ctx, 12, 0_u32);
+        block_on(b.copy_from_async(&a));
+        // source must not be overwritten by destination
+        assert_eq!(a.as_slice(), &[1_u32; 12]);
+        assert_eq!(b.as_slice(), &[1_u32; 12]);
+    }
+
+    #[test]
+    fn memcpy_peer() -> error::Result<()> {
+        let device = Device::nth(0)?;
+        let ctx1 = device.create_context();
+        let ctx2 = device.create_context();
+        let a = DeviceMemory::from_elem(&ctx1, 12, 1_u32);
+        let mut b = DeviceMemory::from_elem(&ctx2, 12, 0_u32);
+        assert!(peer_contexts(b.head_addr(), a.head_addr()).is_some());
+        b.copy_from(&a);
+        assert_eq!(a.as_slice(), b.as_slice());
+        Ok(())
+    }
+
+    #[test]
+    fn memcpy_async_peer() {
+        let device = Device::nth(0).unwrap();
+        let ctx1 = device.create_context();
+        let ctx2 = device.create_context();
+        let a = DeviceMemory::from_elem(&ctx1, 12, 1_u32);
+        let mut b = DeviceMemory::from_elem(&ctx2, 12, 0_u32);
+        block_on(b.copy_from_async(&a));
+        assert_eq!(a.as_slice(), b.as_slice());
+    }
+
+    #[test]
+    fn memcpy_async_d2d() {
         let device = Device::nth(0).unwrap();
         let ctx = device.create_context();
         let a = DeviceMemory::from_elem(&ctx, 12, 1_u32);
@@ -273,9 +383,11 @@ mod tests {
         let fut1 = b1.copy_from_async(&a);
         let fut2 = b2.copy_from_async(&a);
         let fut3 = b3.copy_from_async(&a);
-        fut3.await;
-        fut2.await;
-        fut1.await;
+        block_on(async {
+            fut3.await;
+            fut2.await;
+            fut1.await;
+        });
         assert_eq!(a.as_slice(), b1.as_slice());
         assert_eq!(a.as_slice(), b2.as_slice());
         assert_eq!(a.as_slice(), b3.as_slice());
@@ -2,8 +2,11 @@
 
 use crate::{contexted_call, contexted_new, device::*, error::*, *};
 use cuda::*;
+use num_traits::ToPrimitive;
 use std::ffi::*;
 
+use cuda::CUfunction_attribute_enum as Attr;
+
 /// CUDA Kernel function
 #[derive(Debug)]
 pub struct Kernel<'module> {
@@ -29,6 +32,128 @@ impl Contexted for Kernel<'_> {
     }
 }
 
+impl Kernel<'_> {
+    /// Set preferred cache configuration for this kernel, overwrites the context-level one
+    pub fn set_cache_config(&self, config: CacheConfig) -> Result<()> {
+        unsafe { contexted_call!(self, cuFuncSetCacheConfig, self.func, config.raw()) }
+    }
+
+    /// Set shared memory bank size for this kernel, overwrites the context-level one
+    pub fn set_shared_mem_config(&self, config: SharedMemConfig) -> Result<()> {
+        unsafe { contexted_call!(self, cuFuncSetSharedMemConfig, self.func, config.raw()) }
+    }
+
+    /// Get attributes of this kernel using `cuFuncGetAttribute`
+    pub fn attributes(&self) -> Result<KernelAttributes> {
+        KernelAttributes::decode(|attr| unsafe {
+            contexted_new!(self, cuFuncGetAttribute, attr, self.func)
+        })
+    }
+
+    /// Set the maximum size of dynamically allocated shared memory in bytes
+    ///
+    /// Kernels using more than 48KB of dynamic shared memory must opt in with this setting
+    /// up to `DeviceAttributes::max_shared_memory_per_block_optin`
+    /// minus `KernelAttributes::static_shared_memory`.
+    pub fn set_max_dynamic_shared_memory(&self, bytes: usize) -> Result<()> {
+        let bytes = bytes
+            .to_i32()
+            .ok_or_else(|| AccelError::InvalidLaunchConfig {
+                reason: format!("Too large dynamic shared memory: {} bytes", bytes),
+            })?;
+        self.set_attribute(Attr::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES, bytes)
+    }
+
+    /// Query only `KernelAttributes::max_dynamic_shared_memory` instead of all attributes
+    pub(crate) fn max_dynamic_shared_memory(&self) -> Result<usize> {
+        let attr = Attr::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES;
+        let value = unsafe { contexted_new!(self, cuFuncGetAttribute, attr, self.func)? };
+        Ok(to_count(attr, value)? as usize)
+    }
+
+    /// Set preferred split of the unified L1 cache / shared memory for this kernel
+    ///
+    /// `AccelError::InvalidCarveout` is returned for a percentage larger than 100.
+    pub fn set_preferred_carveout(&self, carveout: SharedMemoryCarveout) -> Result<()> {
+        self.set_attribute(
+            Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+            carveout.raw()?,
+        )
+    }
+
+    fn set_attribute(&self, attr: CUfunction_attribute, value: i32) -> Result<()> {
+        unsafe { contexted_call!(self, cuFuncSetAttribute, self.func, attr, value) }
+    }
+}
+
+/// Typed snapshot of attributes of a kernel
+///
+/// Memory sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelAttributes {
+    /// Maximum number of threads per block this kernel can be launched with,
+    /// which may be less than the device limit due to register usage
+    pub max_threads_per_block: u32,
+    pub registers_per_thread: u32,
+    /// Size of statically allocated shared memory per block
+    pub static_shared_memory: usize,
+    /// Size of user-allocated constant memory
+    pub const_memory: usize,
+    /// Size of local memory per thread
+    pub local_memory: usize,
+    /// PTX virtual architecture version this kernel was compiled for
+    pub ptx_version: ComputeCapability,
+    /// Binary architecture version this kernel was compiled for
+    pub binary_version: ComputeCapability,
+    /// Maximum size of dynamically allocated shared memory, see `Kernel::set_max_dynamic_shared_memory`
+    pub max_dynamic_shared_memory: usize,
+    /// See `Kernel::set_preferred_carveout`
+    pub preferred_carveout: SharedMemoryCarveout,
+}
+
+impl KernelAttributes {
+    pub(crate) fn decode<F>(mut get: F) -> Result<Self>
+    where
+        F: FnMut(CUfunction_attribute) -> Result<i32>,
+    {
+        // carveout is the only attribute which can be negative
+        let carveout_attr = Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT;
+        let raw = get(carveout_attr)?;
+        let preferred_carveout = SharedMemoryCarveout::from_raw(raw).ok_or_else(|| {
+            AccelError::InvalidAttributeValue {
+                attribute: format!("{:?}", carveout_attr),
+                value: raw,
+            }
+        })?;
+        let mut count = |attr: Attr| -> Result<u32> { to_count(attr, get(attr)?) };
+        // versions are reported as `10 * major + minor`
+        let mut version = |attr: Attr| -> Result<ComputeCapability> {
+            let value = count(attr)?;
+            Ok(ComputeCapability::new(value / 10, value % 10))
+        };
+        Ok(KernelAttributes {
+            ptx_version: version(Attr::CU_FUNC_ATTRIBUTE_PTX_VERSION)?,
+            binary_version: version(Attr::CU_FUNC_ATTRIBUTE_BINARY_VERSION)?,
+            max_threads_per_block: count(Attr::CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK)?,
+            registers_per_thread: count(Attr::CU_FUNC_ATTRIBUTE_NUM_REGS)?,
+            static_shared_memory: count(Attr::CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES)? as usize,
+            const_memory: count(Attr::CU_FUNC_ATTRIBUTE_CONST_SIZE_BYTES)? as usize,
+            local_memory: count(Attr::CU_FUNC_ATTRIBUTE_LOCAL_SIZE_BYTES)? as usize,
+            max_dynamic_shared_memory: count(Attr::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES)?
+                as usize,
+            preferred_carveout,
+        })
+    }
+
+    /// Resource usage of this kernel for occupancy calculation
+    pub fn resources(&self) -> KernelResources {
+        KernelResources {
+            registers_per_thread: self.registers_per_thread,
+            static_shared_memory: self.static_shared_memory,
+        }
+    }
+}
+
 /// OOP-like wrapper of `cuModule*` APIs
 #[derive(Debug, Contexted)]
 pub struct Module {
@@ -92,22 +217,141 @@ impl Module {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::PTX_DO_NOTHING;
+    use std::collections::HashMap;
 
     #[test]
     fn load_do_nothing() -> Result<()> {
-        // generated by do_nothing example in accel-derive
-        let ptx = r#"
-        .version 3.2
-        .target sm_30
-        .address_size 64
-        .visible .entry do_nothing()
-        {
-          ret;
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let _mod = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        Ok(())
+    }
+
+    fn table() -> HashMap<Attr, i32> {
+        [
+            (Attr::CU_FUNC_ATTRIBUTE_MAX_THREADS_PER_BLOCK, 768),
+            (Attr::CU_FUNC_ATTRIBUTE_SHARED_SIZE_BYTES, 4096),
+            (Attr::CU_FUNC_ATTRIBUTE_CONST_SIZE_BYTES, 16),
+            (Attr::CU_FUNC_ATTRIBUTE_LOCAL_SIZE_BYTES, 8),
+            (Attr::CU_FUNC_ATTRIBUTE_NUM_REGS, 72),
+            (Attr::CU_FUNC_ATTRIBUTE_PTX_VERSION, 30),
+            (Attr::CU_FUNC_ATTRIBUTE_BINARY_VERSION, 75),
+            (Attr::CU_FUNC_ATTRIBUTE_CACHE_MODE_CA, 0),
+            (
+                Attr::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES,
+                48 * 1024 - 4096,
+            ),
+            (Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT, -1),
+        ]
+        .iter()
+        .cloned()
+        .collect()
+    }
+
+    #[test]
+    fn decode_attributes() -> Result<()> {
+        let table = table();
+        let attr = KernelAttributes::decode(|attr| Ok(table[&attr]))?;
+        assert_eq!(attr.max_threads_per_block, 768);
+        assert_eq!(attr.registers_per_thread, 72);
+        assert_eq!(attr.static_shared_memory, 4096);
+        assert_eq!(attr.const_memory, 16);
+        assert_eq!(attr.local_memory, 8);
+        assert_eq!(attr.ptx_version, ComputeCapability::new(3, 0));
+        assert_eq!(attr.binary_version, ComputeCapability::new(7, 5));
+        assert_eq!(attr.max_dynamic_shared_memory, 44 * 1024);
+        assert_eq!(attr.preferred_carveout, SharedMemoryCarveout::Default);
+        assert_eq!(
+            attr.resources(),
+            KernelResources {
+                registers_per_thread: 72,
+                static_shared_memory: 4096
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn decode_carveout() -> Result<()> {
+        // (raw, expected)
+        let cases = [
+            (-1, SharedMemoryCarveout::Default),
+            (0, SharedMemoryCarveout::MaxL1),
+            (25, SharedMemoryCarveout::Percent(25)),
+            (100, SharedMemoryCarveout::MaxShared),
+        ];
+        for &(raw, expected) in &cases {
+            let mut table = table();
+            table.insert(
+                Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+                raw,
+            );
+            let attr = KernelAttributes::decode(|attr| Ok(table[&attr]))?;
+            assert_eq!(attr.preferred_carveout, expected);
         }
-        "#;
+        Ok(())
+    }
+
+    #[test]
+    fn decode_negative() {
+        // (attribute, invalid value)
+        let cases = [
+            (Attr::CU_FUNC_ATTRIBUTE_NUM_REGS, -1),
+            (Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT, -2),
+            (
+                Attr::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+                101,
+            ),
+        ];
+        for &(attr, value) in &cases {
+            let mut table = table();
+            table.insert(attr, value);
+            match KernelAttributes::decode(|attr| Ok(table[&attr])) {
+                Err(AccelError::InvalidAttributeValue { value: v, .. }) => assert_eq!(v, value),
+                result => panic!("Unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn kernel_attributes() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+        let attr = kernel.attributes()?;
+        // the driver reports the target `sm_30` as the PTX version instead of `.version 3.2`
+        assert_eq!(attr.ptx_version, ComputeCapability::new(3, 0));
+        // JIT-compiled for this device
+        assert_eq!(attr.binary_version, device.compute_capability()?);
+        assert_eq!(attr.static_shared_memory, 0);
+        assert_eq!(attr.const_memory, 0);
+        assert_eq!(attr.local_memory, 0);
+        assert!(attr.max_threads_per_block > 0);
+        assert!(attr.max_threads_per_block <= device.attributes()?.max_threads_per_block);
+        assert_eq!(attr.preferred_carveout, SharedMemoryCarveout::Default);
+
+        kernel.set_max_dynamic_shared_memory(1024)?;
+        assert!(kernel.set_max_dynamic_shared_memory(usize::MAX).is_err());
+        kernel.set_preferred_carveout(SharedMemoryCarveout::MaxShared)?;
+        assert!(kernel
+            .set_preferred_carveout(SharedMemoryCarveout::Percent(200))
+            .is_err());
+        let attr = kernel.attributes()?;
+        assert_eq!(attr.max_dynamic_shared_memory, 1024);
+        assert_eq!(attr.preferred_carveout, SharedMemoryCarveout::MaxShared);
+        Ok(())
+    }
+
+    #[test]
+    fn kernel_cache_config() -> Result<()> {
         let device = Device::nth(0)?;
         let ctx = device.create_context();
-        let _mod = Module::from_str(&ctx, ptx)?;
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+        kernel.set_cache_config(CacheConfig::PreferShared)?;
+        kernel.set_shared_mem_config(SharedMemConfig::EightByteBankSize)?;
         Ok(())
     }
 }
@@ -0,0 +1,595 @@
+//! Occupancy, i.e. how many warps of a kernel can be resident on a multiprocessor (SM) at once
+//!
+//! `Kernel::max_active_blocks_per_sm` and `Kernel::suggest_block_size` ask the driver about a loaded kernel.
+//! [OccupancyLimits] computes the same in pure Rust from resource usage of a kernel and device limits
+//! in the manner of the CUDA Occupancy Calculator, which is useful to explore launch configurations offline:
+//!
+//! ```
+//! use accel::*;
+//! let limits = OccupancyLimits {
+//!     warp_size: 32,
+//!     multiprocessor_count: 40,
+//!     max_threads_per_block: 1024,
+//!     max_threads_per_multiprocessor: 1024,
+//!     max_blocks_per_multiprocessor: 16,
+//!     max_registers_per_block: 65536,
+//!     max_registers_per_multiprocessor: 65536,
+//!     register_allocation_unit: 256,
+//!     max_shared_memory_per_block: 65536,
+//!     max_shared_memory_per_multiprocessor: 65536,
+//!     shared_memory_allocation_unit: 256,
+//!     reserved_shared_memory_per_block: 0,
+//! };
+//! let resources = KernelResources { registers_per_thread: 72, static_shared_memory: 0 };
+//! let occupancy = limits.occupancy(&resources, 256, 0).unwrap();
+//! assert_eq!(occupancy.active_blocks_per_multiprocessor, 3);
+//! assert_eq!(occupancy.limiter, OccupancyLimiter::Registers);
+//! assert_eq!(occupancy.ratio(), 0.75);
+//! ```
+//!
+//! [OccupancyLimits]: ./struct.OccupancyLimits.html
+
+use crate::{contexted_call, device::*, error::*, *};
+use cuda::*;
+use num_traits::{PrimInt, ToPrimitive};
+use std::fmt;
+
+/// Maximum number of registers a thread can use
+const MAX_REGISTERS_PER_THREAD: u32 = 255;
+
+/// Resource usage of a kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KernelResources {
+    pub registers_per_thread: u32,
+    /// Size of statically allocated shared memory per block in bytes
+    pub static_shared_memory: usize,
+}
+
+/// Limits of a device which determine occupancy
+///
+/// Memory sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OccupancyLimits {
+    pub warp_size: u32,
+    pub multiprocessor_count: u32,
+    pub max_threads_per_block: u32,
+    pub max_threads_per_multiprocessor: u32,
+    pub max_blocks_per_multiprocessor: u32,
+    pub max_registers_per_block: u32,
+    pub max_registers_per_multiprocessor: u32,
+    /// Registers are allocated to each warp in multiples of this
+    pub register_allocation_unit: u32,
+    /// Maximum shared memory of a block including dynamic one
+    pub max_shared_memory_per_block: usize,
+    pub max_shared_memory_per_multiprocessor: usize,
+    /// Shared memory is allocated to each block in multiples of this
+    pub shared_memory_allocation_unit: usize,
+    /// Shared memory reserved by the system for each block
+    pub reserved_shared_memory_per_block: usize,
+}
+
+impl OccupancyLimits {
+    /// Limits from attributes of a device
+    ///
+    /// Allocation granularities and the maximum number of blocks,
+    /// which are not reported by the driver, are determined from the compute capability.
+    pub fn from_attributes(attr: &DeviceAttributes) -> Self {
+        let cc = attr.compute_capability;
+        let max_blocks_per_multiprocessor = match (cc.major, cc.minor) {
+            (3, _) | (7, 5) | (8, 6) | (8, 7) => 16,
+            (8, 9) => 24,
+            _ => 32,
+        };
+        let ampere_or_later = cc >= ComputeCapability::new(8, 0);
+        OccupancyLimits {
+            warp_size: attr.warp_size,
+            multiprocessor_count: attr.multiprocessor_count,
+            max_threads_per_block: attr.max_threads_per_block,
+            max_threads_per_multiprocessor: attr.max_threads_per_multiprocessor,
+            max_blocks_per_multiprocessor,
+            max_registers_per_block: attr.max_registers_per_block,
+            max_registers_per_multiprocessor: attr.max_registers_per_multiprocessor,
+            register_allocation_unit: 256,
+            max_shared_memory_per_block: attr
+                .max_shared_memory_per_block_optin
+                .max(attr.max_shared_memory_per_block),
+            max_shared_memory_per_multiprocessor: attr.max_shared_memory_per_multiprocessor,
+            shared_memory_allocation_unit: if ampere_or_later { 128 } else { 256 },
+            reserved_shared_memory_per_block: if ampere_or_later { 1024 } else { 0 },
+        }
+    }
+
+    /// Check limits used as divisors, which are zero e.g. in zeroed attributes of a mock device
+    fn validate(&self) -> Result<()> {
+        let divisors = [
+            ("warp_size", self.warp_size as usize),
+            (
+                "register_allocation_unit",
+                self.register_allocation_unit as usize,
+            ),
+            (
+                "shared_memory_allocation_unit",
+                self.shared_memory_allocation_unit,
+            ),
+        ];
+        match divisors.iter().find(|(_, value)| *value == 0) {
+            Some((name, _)) => Err(AccelError::InvalidOccupancyLimits {
+                reason: format!("{} is zero", name),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns an error if `warp_size` is zero
+    pub fn max_warps_per_multiprocessor(&self) -> Result<u32> {
+        self.validate()?;
+        Ok(self.max_threads_per_multiprocessor / self.warp_size)
+    }
+
+    /// Occupancy of a kernel launched with `block_size` threads per block
+    ///
+    /// Returns an error if `warp_size` or an allocation unit is zero.
+    pub fn occupancy(
+        &self,
+        resources: &KernelResources,
+        block_size: u32,
+        dynamic_shared_memory: usize,
+    ) -> Result<Occupancy> {
+        let max_warps = self.max_warps_per_multiprocessor()?;
+        if block_size == 0 || block_size > self.max_threads_per_block {
+            return Ok(Occupancy {
+                active_blocks_per_multiprocessor: 0,
+                active_warps_per_multiprocessor: 0,
+                max_warps_per_multiprocessor: max_warps,
+                limiter: OccupancyLimiter::BlockSize,
+            });
+        }
+        let warps_per_block = div_ceil(block_size, self.warp_size)?;
+
+        let by_warps = max_warps / warps_per_block;
+        let by_blocks = self.max_blocks_per_multiprocessor;
+
+        let regs = resources.registers_per_thread;
+        let by_registers = if regs == 0 {
+            u32::MAX
+        } else if regs > MAX_REGISTERS_PER_THREAD
+            || !matches!(
+                regs.checked_mul(warps_per_block)
+                    .and_then(|regs| regs.checked_mul(self.warp_size)),
+                Some(regs) if regs <= self.max_registers_per_block
+            )
+        {
+            0
+        } else {
+            let per_warp = div_ceil(regs * self.warp_size, self.register_allocation_unit)?
+                * self.register_allocation_unit;
+            (self.max_registers_per_multiprocessor / per_warp) / warps_per_block
+        };
+
+        let shared = resources
+            .static_shared_memory
+            .checked_add(dynamic_shared_memory)
+            .filter(|&shared| shared <= self.max_shared_memory_per_block)
+            .and_then(|shared| shared.checked_add(self.reserved_shared_memory_per_block));
+        let by_shared_memory = match shared {
+            Some(shared) => {
+                let per_block = div_ceil(shared, self.shared_memory_allocation_unit)?
+                    * self.shared_memory_allocation_unit;
+                self.max_shared_memory_per_multiprocessor
+                    .checked_div(per_block)
+                    .map_or(u32::MAX, |blocks| blocks as u32)
+            }
+            None => 0,
+        };
+
+        // the first one is reported if several limits are tight
+        let limits = [
+            (by_warps, OccupancyLimiter::Warps),
+            (by_blocks, OccupancyLimiter::Blocks),
+            (by_registers, OccupancyLimiter::Registers),
+            (by_shared_memory, OccupancyLimiter::SharedMemory),
+        ];
+        let (blocks, limiter) = *limits.iter().min_by_key(|(blocks, _)| *blocks).unwrap();
+        Ok(Occupancy {
+            active_blocks_per_multiprocessor: blocks,
+            active_warps_per_multiprocessor: blocks * warps_per_block,
+            max_warps_per_multiprocessor: max_warps,
+            limiter,
+        })
+    }
+
+    /// Block size maximizing occupancy, where larger one is preferred if occupancies are same
+    ///
+    /// This returns `None` if the kernel cannot be launched with any block size,
+    /// and an error if `warp_size` or an allocation unit is zero.
+    pub fn suggest_block_size(
+        &self,
+        resources: &KernelResources,
+        dynamic_shared_memory: usize,
+    ) -> Result<Option<BlockSizeSuggestion>> {
+        self.validate()?;
+        let mut best: Option<(Occupancy, u32)> = None;
+        for block_size in (1..=self.max_threads_per_block / self.warp_size)
+            .rev()
+            .map(|w| w * self.warp_size)
+        {
+            let occupancy = self.occupancy(resources, block_size, dynamic_shared_memory)?;
+            let is_better = match best {
+                Some((b, _)) => {
+                    occupancy.active_warps_per_multiprocessor > b.active_warps_per_multiprocessor
+                }
+                None => occupancy.active_blocks_per_multiprocessor > 0,
+            };
+            if is_better {
+                best = Some((occupancy, block_size));
+            }
+        }
+        Ok(best.map(|(occupancy, block_size)| BlockSizeSuggestion {
+            block_size,
+            min_grid_size: occupancy.active_blocks_per_multiprocessor * self.multiprocessor_count,
+        }))
+    }
+}
+
+/// `ceil(a / b)` in checked arithmetic, since `div_ceil` is not available on the pinned toolchain
+fn div_ceil<T: PrimInt + fmt::Display>(a: T, b: T) -> Result<T> {
+    b.checked_sub(&T::one())
+        .and_then(|b1| a.checked_add(&b1))
+        .map(|a| a / b)
+        .ok_or_else(|| AccelError::InvalidOccupancyLimits {
+            reason: format!("Cannot compute ceil({} / {})", a, b),
+        })
+}
+
+/// Number of threads in a block as the driver takes, which must not overflow `i32`
+fn threads_per_block(block: Block) -> Result<i32> {
+    block
+        .x
+        .checked_mul(block.y)
+        .and_then(|threads| threads.checked_mul(block.z))
+        .and_then(|threads| threads.to_i32())
+        .ok_or_else(|| AccelError::InvalidLaunchConfig {
+            reason: format!(
+                "Threads per block of ({}, {}, {}) overflows",
+                block.x, block.y, block.z
+            ),
+        })
+}
+
+/// Resource limiting the number of active blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OccupancyLimiter {
+    /// Block size is zero or exceeds the maximum
+    BlockSize,
+    /// Maximum number of warps (threads) on a multiprocessor
+    Warps,
+    /// Maximum number of blocks on a multiprocessor
+    Blocks,
+    Registers,
+    SharedMemory,
+}
+
+/// Result of the occupancy calculation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Occupancy {
+    pub active_blocks_per_multiprocessor: u32,
+    pub active_warps_per_multiprocessor: u32,
+    pub max_warps_per_multiprocessor: u32,
+    pub limiter: OccupancyLimiter,
+}
+
+impl Occupancy {
+    /// Ratio of active warps to the maximum in `[0, 1]`
+    ///
+    /// This is zero if the maximum is zero, e.g. for limits of a mock device.
+    pub fn ratio(&self) -> f64 {
+        if self.max_warps_per_multiprocessor == 0 {
+            return 0.0;
+        }
+        f64::from(self.active_warps_per_multiprocessor)
+            / f64::from(self.max_warps_per_multiprocessor)
+    }
+}
+
+/// Launch configuration maximizing occupancy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSizeSuggestion {
+    pub block_size: u32,
+    /// Minimum grid size to fill all multiprocessors with the maximum occupancy
+    pub min_grid_size: u32,
+}
+
+impl Kernel<'_> {
+    /// Number of blocks which can be active on a multiprocessor at once
+    pub fn max_active_blocks_per_sm(
+        &self,
+        block: impl Into<Block>,
+        dynamic_shared_memory: usize,
+    ) -> Result<u32> {
+        let block_size = threads_per_block(block.into())?;
+        let mut blocks = 0;
+        unsafe {
+            contexted_call!(
+                self,
+                cuOccupancyMaxActiveBlocksPerMultiprocessor,
+                &mut blocks as *mut _,
+                self.func,
+                block_size,
+                dynamic_shared_memory
+            )?;
+        }
+        Ok(blocks as u32)
+    }
+
+    /// Block size maximizing occupancy of this kernel suggested by the driver
+    ///
+    /// ```
+    /// # use accel::*;
+    /// # const PTX: &str = ".version 3.2\n.target sm_30\n.address_size 64\n.visible .entry do_nothing() { ret; }";
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// let module = Module::from_str(&ctx, PTX).unwrap();
+    /// let kernel = module.get_kernel("do_nothing").unwrap();
+    /// let suggestion = kernel.suggest_block_size(0).unwrap();
+    /// let n = 1 << 20;
+    /// let grid = (n + suggestion.block_size - 1) / suggestion.block_size;
+    /// println!("grid = {}, block = {}", grid, suggestion.block_size);
+    /// ```
+    pub fn suggest_block_size(&self, dynamic_shared_memory: usize) -> Result<BlockSizeSuggestion> {
+        let mut min_grid_size = 0;
+        let mut block_size = 0;
+        unsafe {
+            contexted_call!(
+                self,
+                cuOccupancyMaxPotentialBlockSize,
+                &mut min_grid_size as *mut _,
+                &mut block_size as *mut _,
+                self.func,
+                None,
+                dynamic_shared_memory,
+                0 /* no limit of block size */
+            )?;
+        }
+        Ok(BlockSizeSuggestion {
+            block_size: block_size as u32,
+            min_grid_size: min_grid_size as u32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{turing_attributes, PTX_DO_NOTHING};
+    use cuda::CUdevice_attribute_enum as Attr;
+
+    /// sm_75
+    fn turing() -> OccupancyLimits {
+        OccupancyLimits::from_attributes(&turing_attributes())
+    }
+
+    /// sm_80, i.e. A100
+    fn ampere() -> OccupancyLimits {
+        let mut table = crate::tests::turing();
+        table.extend(
+            [
+                (Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR, 8),
+                (Attr::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR, 0),
+                (Attr::CU_DEVICE_ATTRIBUTE_MULTIPROCESSOR_COUNT, 108),
+                (
+                    Attr::CU_DEVICE_ATTRIBUTE_MAX_THREADS_PER_MULTIPROCESSOR,
+                    2048,
+                ),
+                (
+                    Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_BLOCK_OPTIN,
+                    166912,
+                ),
+                (
+                    Attr::CU_DEVICE_ATTRIBUTE_MAX_SHARED_MEMORY_PER_MULTIPROCESSOR,
+                    167936,
+                ),
+            ]
+            .iter()
+            .cloned(),
+        );
+        OccupancyLimits::from_attributes(
+            &DeviceAttributes::decode(|attr| Ok(table[&attr])).unwrap(),
+        )
+    }
+
+    #[test]
+    fn occupancy() -> Result<()> {
+        use OccupancyLimiter::*;
+        // (limits, registers, static smem, block size, dynamic smem, active blocks, limiter)
+        let table = [
+            (turing(), 32, 0, 256, 0, 4, Warps),
+            (turing(), 0, 0, 1024, 0, 1, Warps),
+            (turing(), 72, 0, 256, 0, 3, Registers),
+            (turing(), 16, 20 * 1024, 128, 0, 3, SharedMemory),
+            (turing(), 16, 0, 128, 20 * 1024, 3, SharedMemory),
+            (turing(), 16, 0, 32, 0, 16, Blocks),
+            (turing(), 16, 0, 33, 0, 16, Warps),
+            (turing(), 16, 0, 0, 0, 0, BlockSize),
+            (turing(), 16, 0, 2048, 0, 0, BlockSize),
+            (turing(), 256, 0, 32, 0, 0, Registers),
+            (turing(), 128, 0, 1024, 0, 0, Registers),
+            (turing(), 16, 0, 32, 70 * 1024, 0, SharedMemory),
+            (ampere(), 32, 0, 256, 0, 8, Warps),
+            (ampere(), 32, 0, 256, 48 * 1024, 3, SharedMemory),
+            (ampere(), 16, 0, 32, 0, 32, Blocks),
+            (ampere(), 16, 0, 32, 4 * 1024, 32, Blocks),
+            (ampere(), 16, 0, 32, 5 * 1024, 27, SharedMemory),
+        ];
+        for &(
+            limits,
+            registers_per_thread,
+            static_shared_memory,
+            block,
+            dynamic,
+            blocks,
+            limiter,
+        ) in &table
+        {
+            let resources = KernelResources {
+                registers_per_thread,
+                static_shared_memory,
+            };
+            let occupancy = limits.occupancy(&resources, block, dynamic)?;
+            assert_eq!(
+                (
+                    occupancy.active_blocks_per_multiprocessor,
+                    occupancy.limiter
+                ),
+                (blocks, limiter),
+                "registers = {}, smem = {}, block = {}, dynamic smem = {}",
+                registers_per_thread,
+                static_shared_memory,
+                block,
+                dynamic
+            );
+            assert_eq!(
+                occupancy.active_warps_per_multiprocessor,
+                blocks * ((block + 31) / 32)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ratio() -> Result<()> {
+        let resources = KernelResources {
+            registers_per_thread: 72,
+            static_shared_memory: 0,
+        };
+        let occupancy = turing().occupancy(&resources, 256, 0)?;
+        assert_eq!(occupancy.active_warps_per_multiprocessor, 24);
+        assert_eq!(occupancy.ratio(), 0.75);
+        let occupancy = turing().occupancy(&resources, 0, 0)?;
+        assert_eq!(occupancy.ratio(), 0.0);
+        // no warp can be resident, e.g. max_threads_per_multiprocessor is zero
+        let limits = OccupancyLimits {
+            max_threads_per_multiprocessor: 0,
+            ..turing()
+        };
+        let occupancy = limits.occupancy(&resources, 256, 0)?;
+        assert_eq!(occupancy.max_warps_per_multiprocessor, 0);
+        assert_eq!(occupancy.ratio(), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn suggest_block_size() -> Result<()> {
+        // (limits, registers, dynamic smem, block size, min grid size)
+        let table = [
+            (turing(), 32, 0, 1024, 40),
+            (turing(), 72, 0, 896, 40),
+            (turing(), 16, 40 * 1024, 1024, 40),
+            (ampere(), 32, 0, 1024, 216),
+            (ampere(), 64, 0, 1024, 108),
+        ];
+        for &(limits, registers_per_thread, dynamic, block_size, min_grid_size) in &table {
+            let resources = KernelResources {
+                registers_per_thread,
+                static_shared_memory: 0,
+            };
+            assert_eq!(
+                limits.suggest_block_size(&resources, dynamic)?,
+                Some(BlockSizeSuggestion {
+                    block_size,
+                    min_grid_size
+                }),
+                "registers = {}, dynamic smem = {}",
+                registers_per_thread,
+                dynamic
+            );
+        }
+        let resources = KernelResources {
+            registers_per_thread: 16,
+            static_shared_memory: 0,
+        };
+        assert_eq!(turing().suggest_block_size(&resources, 1 << 20)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn zero_limits() {
+        let resources = KernelResources {
+            registers_per_thread: 16,
+            static_shared_memory: 0,
+        };
+        let zeroed = [
+            OccupancyLimits {
+                warp_size: 0,
+                ..turing()
+            },
+            OccupancyLimits {
+                register_allocation_unit: 0,
+                ..turing()
+            },
+            OccupancyLimits {
+                shared_memory_allocation_unit: 0,
+                ..turing()
+            },
+        ];
+        for limits in &zeroed {
+            match limits.occupancy(&resources, 256, 0) {
+                Err(AccelError::InvalidOccupancyLimits { .. }) => {}
+                result => panic!("Unexpected result: {:?}", result),
+            }
+            assert!(limits.suggest_block_size(&resources, 0).is_err());
+        }
+        assert!(zeroed[0].max_warps_per_multiprocessor().is_err());
+    }
+
+    #[test]
+    fn div_ceil_overflow() {
+        assert_eq!(div_ceil(7_u32, 4).unwrap(), 2);
+        assert_eq!(div_ceil(8_usize, 4).unwrap(), 2);
+        assert!(div_ceil(u32::MAX, 2).is_err());
+        assert!(div_ceil(1_u32, 0).is_err());
+    }
+
+    #[test]
+    fn threads_per_block() {
+        assert_eq!(
+            super::threads_per_block(Block::xyz(32, 8, 4)).unwrap(),
+            1024
+        );
+        assert_eq!(
+            super::threads_per_block(Block::xyz(1 << 15, 1 << 15, 1)).unwrap(),
+            1 << 30
+        );
+        for &block in &[
+            Block::xyz(1 << 16, 1 << 15, 1),
+            Block::xyz(1 << 16, 1 << 16, 1),
+            Block::xyz(u32::MAX, 2, 1),
+        ] {
+            match super::threads_per_block(block) {
+                Err(AccelError::InvalidLaunchConfig { .. }) => {}
+                result => panic!("Unexpected result for {:?}: {:?}", block, result),
+            }
+        }
+    }
+
+    #[test]
+    fn from_attributes() -> Result<()> {
+        let device = Device::nth(0)?;
+        let limits = OccupancyLimits::from_attributes(&device.attributes()?);
+        assert_eq!(limits.warp_size, 32);
+        assert!(limits.max_warps_per_multiprocessor()? >= 32);
+        Ok(())
+    }
+
+    #[test]
+    fn driver_occupancy() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        let module = Module::from_str(&ctx, PTX_DO_NOTHING)?;
+        let kernel = module.get_kernel("do_nothing")?;
+        let suggestion = kernel.suggest_block_size(0)?;
+        assert!(suggestion.block_size > 0);
+        assert!(suggestion.min_grid_size > 0);
+        assert!(kernel.max_active_blocks_per_sm(suggestion.block_size, 0)? > 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Integration with async runtimes, enabled by `tokio` and `async-std` features
+//!
+//! Futures of accel work on any executor without these features.
+//! These modules run blocking driver calls, e.g. `cuCtxSynchronize`,
+//! on the thread pool of each runtime for blocking tasks, instead of its worker threads.
+
+#[cfg(feature = "tokio")]
+pub mod tokio {
+    use crate::{device::*, error::*};
+
+    /// Wait until all tasks in the context have been completed without blocking worker threads of tokio
+    ///
+    /// A panic while waiting is resumed in the caller.
+    ///
+    /// ```
+    /// # use accel::*;
+    /// # #[tokio::main(basic_scheduler)]
+    /// # async fn main() {
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// accel::runtime::tokio::sync(ctx).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn sync<C: Contexted + Send + 'static>(ctx: C) -> Result<()> {
+        match ::tokio::task::spawn_blocking(move || ctx.sync()).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("Blocking task of tokio is cancelled: {:?}", e),
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+pub mod async_std {
+    use crate::{device::*, error::*};
+
+    /// Wait until all tasks in the context have been completed without blocking worker threads of async-std
+    ///
+    /// ```
+    /// # use accel::*;
+    /// # #[async_std::main]
+    /// # async fn main() {
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// accel::runtime::async_std::sync(ctx).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn sync<C: Contexted + Send + 'static>(ctx: C) -> Result<()> {
+        ::async_std::task::spawn_blocking(move || ctx.sync()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use crate::{device::*, error::*};
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn sync_tokio() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        super::tokio::sync(ctx.clone()).await?;
+        super::tokio::sync(ctx.get_ref()).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "async-std")]
+    #[async_std::test]
+    async fn sync_async_std() -> Result<()> {
+        let device = Device::nth(0)?;
+        let ctx = device.create_context();
+        super::async_std::sync(ctx.clone()).await?;
+        super::async_std::sync(ctx.get_ref()).await?;
+        Ok(())
+    }
+}
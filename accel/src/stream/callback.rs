@@ -0,0 +1,216 @@
+//! Host callbacks ordered with tasks in a stream
+
+use super::*;
+use std::{
+    any::Any,
+    os::raw::c_void,
+    panic::{self, AssertUnwindSafe},
+};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Data passed to the driver, which is freed when the callback is called
+struct CallbackData {
+    callback: Callback,
+    /// Shared with the stream to report a panic in the callback
+    panic: Arc<Mutex<Option<String>>>,
+}
+
+impl Stream {
+    /// Register a host function called after all tasks issued into this stream before have been completed
+    ///
+    /// Tasks issued after this call wait until the callback returns.
+    /// The callback runs on a thread managed by CUDA driver,
+    /// and it must not call CUDA APIs, or it will cause deadlock.
+    ///
+    /// The callback is called exactly once, and `AccelError::CallbackInCapture` is returned
+    /// if this stream is capturing, since a graph may call it many times.
+    /// A panic in the callback cannot unwind into the driver.
+    /// It is caught, and reported by the next `Stream::sync` (or the future of this stream)
+    /// as `AccelError::HostCallbackPanicked`.
+    ///
+    /// ```
+    /// # use accel::*;
+    /// use std::sync::mpsc::channel;
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// let stream = Stream::new(ctx.get_ref());
+    /// let (tx, rx) = channel();
+    /// stream.add_callback(move || tx.send(1).unwrap()).unwrap();
+    /// assert_eq!(rx.recv().unwrap(), 1);
+    /// ```
+    pub fn add_callback<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.is_capturing()? {
+            return Err(AccelError::CallbackInCapture);
+        }
+        let data = Box::into_raw(Box::new(CallbackData {
+            callback: Box::new(f),
+            panic: self.callback_panic.clone(),
+        }));
+        let result = unsafe {
+            contexted_call!(
+                self,
+                cuLaunchHostFunc,
+                self.stream,
+                Some(host_callback),
+                data as *mut c_void
+            )
+        };
+        if result.is_err() {
+            // the callback will never be called
+            drop(unsafe { Box::from_raw(data) });
+        }
+        result
+    }
+
+    /// Detach host callbacks registered so far from this stream before it is passed to another user
+    ///
+    /// A panic in them is still logged, but never reported by this stream.
+    pub(super) fn reset_callback_panic(&mut self) {
+        self.callback_panic = Arc::new(Mutex::new(None));
+    }
+
+    /// Report the first panic in host callbacks since the last call
+    pub(super) fn take_callback_panic(&self) -> Result<()> {
+        match lock_panic(&self.callback_panic).take() {
+            Some(message) => Err(AccelError::HostCallbackPanicked { message }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn lock_panic(panic: &Mutex<Option<String>>) -> std::sync::MutexGuard<'_, Option<String>> {
+    // The message is always consistent even if another thread panics
+    panic
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Trampoline called by CUDA driver with the pointer created in `Stream::add_callback`
+///
+/// Safety
+/// ------
+/// - `data` must be created by `Stream::add_callback`, and this must be called only once for it
+unsafe extern "C" fn host_callback(data: *mut c_void) {
+    let CallbackData { callback, panic } = *Box::from_raw(data as *mut CallbackData);
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(callback)) {
+        let message = panic_message(payload.as_ref()).to_string();
+        log::error!("Panic in a host callback of CUDA stream: {}", message);
+        // keep the first one
+        lock_panic(&panic).get_or_insert(message);
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<Any>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc,
+    };
+
+    fn into_data<F: FnOnce() + Send + 'static>(
+        f: F,
+        panic: &Arc<Mutex<Option<String>>>,
+    ) -> *mut c_void {
+        Box::into_raw(Box::new(CallbackData {
+            callback: Box::new(f),
+            panic: panic.clone(),
+        })) as *mut c_void
+    }
+
+    #[test]
+    fn trampoline() {
+        let panic = Arc::new(Mutex::new(None));
+        let count = Arc::new(AtomicUsize::new(0));
+        let c = count.clone();
+        let data = into_data(move || drop(c.fetch_add(1, Ordering::SeqCst)), &panic);
+        unsafe { host_callback(data) };
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(*panic.lock().unwrap(), None);
+    }
+
+    #[test]
+    fn trampoline_catch_panic() {
+        let panic = Arc::new(Mutex::new(None));
+        let (tx, rx) = channel::<()>();
+        // the sender is dropped while unwinding
+        let data = into_data(move || panic!("panic in callback {:?}", tx), &panic);
+        unsafe { host_callback(data) };
+        assert!(rx.recv().is_err());
+        // only the first panic is kept
+        unsafe { host_callback(into_data(|| panic!("second"), &panic)) };
+        let message = panic.lock().unwrap().take().unwrap();
+        assert!(message.starts_with("panic in callback"), "{}", message);
+    }
+
+    #[test]
+    fn message() {
+        let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static");
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 1");
+    }
+
+    #[test]
+    fn add_callback() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        let (tx, rx) = channel();
+        for i in 0..4 {
+            let tx = tx.clone();
+            stream.add_callback(move || tx.send(i).unwrap())?;
+        }
+        stream.sync()?;
+        drop(tx);
+        // called in the issued order
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn add_callback_panic() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        stream.add_callback(|| panic!("This panic is caught"))?;
+        match stream.sync() {
+            Err(AccelError::HostCallbackPanicked { message }) => {
+                assert_eq!(message, "This panic is caught")
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        // reported only once
+        stream.sync()?;
+        Ok(())
+    }
+
+    #[test]
+    fn add_callback_in_capture() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        stream.begin_capture()?;
+        match stream.add_callback(|| {}) {
+            Err(AccelError::CallbackInCapture) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        let _graph = stream.end_capture()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+//! Share events between processes
+
+use super::*;
+use crate::memory::IPC_HANDLE_SIZE;
+
+/// Handle of an event shared with other processes
+///
+/// ```
+/// # use accel::*;
+/// let handle = IpcEventHandle([7; 64]);
+/// let bytes = handle.to_bytes();
+/// assert_eq!(IpcEventHandle::from_bytes(&bytes).unwrap(), handle);
+/// assert!(IpcEventHandle::from_bytes(&bytes[1..]).is_err());
+/// ```
+#[derive(Clone, Copy)]
+pub struct IpcEventHandle(pub [u8; IPC_HANDLE_SIZE]);
+
+impl PartialEq for IpcEventHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+impl Eq for IpcEventHandle {}
+
+impl std::fmt::Debug for IpcEventHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("IpcEventHandle").field(&&self.0[..]).finish()
+    }
+}
+
+impl IpcEventHandle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != IPC_HANDLE_SIZE {
+            return Err(AccelError::InvalidIpcHandle {
+                reason: format!(
+                    "{} bytes are given while {} bytes are expected",
+                    bytes.len(),
+                    IPC_HANDLE_SIZE
+                ),
+            });
+        }
+        // `TryFrom<&[u8]>` is not implemented for arrays larger than 32 on the pinned toolchain
+        let mut raw = [0_u8; IPC_HANDLE_SIZE];
+        raw.copy_from_slice(bytes);
+        Ok(IpcEventHandle(raw))
+    }
+}
+
+impl Event {
+    /// Create a new event which can be shared with other processes
+    ///
+    /// Interprocess events are created without timing as CUDA requires.
+    pub fn interprocess(context: ContextRef) -> Result<Self> {
+        Self::with_flags(
+            context,
+            CUevent_flags_enum::CU_EVENT_BLOCKING_SYNC as u32
+                | CUevent_flags_enum::CU_EVENT_DISABLE_TIMING as u32
+                | CUevent_flags_enum::CU_EVENT_INTERPROCESS as u32,
+        )
+    }
+
+    /// Export a handle to share this event with other processes
+    ///
+    /// Only events created by `Event::interprocess` can be exported.
+    pub fn ipc_handle(&self) -> Result<IpcEventHandle> {
+        let handle = unsafe { contexted_new!(self, cuIpcGetEventHandle, self.event)? };
+        let mut raw = [0_u8; IPC_HANDLE_SIZE];
+        for (byte, &r) in raw.iter_mut().zip(handle.reserved.iter()) {
+            *byte = r as u8;
+        }
+        Ok(IpcEventHandle(raw))
+    }
+
+    /// Open an event exported by another process
+    ///
+    /// The opened event can be waited by `Stream::wait_event` or `Event::sync`, but cannot be used for timing.
+    pub fn open_ipc(context: ContextRef, handle: &IpcEventHandle) -> Result<Self> {
+        let mut raw = CUipcEventHandle_st {
+            reserved: [0; IPC_HANDLE_SIZE],
+        };
+        for (r, &byte) in raw.reserved.iter_mut().zip(handle.0.iter()) {
+            *r = byte as _;
+        }
+        let event = unsafe { contexted_new!(&context, cuIpcOpenEventHandle, raw)? };
+        Ok(Event {
+            event,
+            timing: false,
+            context,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let mut raw = [0_u8; IPC_HANDLE_SIZE];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = 255 - i as u8;
+        }
+        let bytes = IpcEventHandle(raw).to_bytes();
+        assert_eq!(bytes, raw.to_vec());
+        assert_eq!(IpcEventHandle::from_bytes(&bytes).unwrap().0[..], raw[..]);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        for &len in &[0, 63, 65, 76] {
+            match IpcEventHandle::from_bytes(&vec![0; len]) {
+                Err(AccelError::InvalidIpcHandle { .. }) => {}
+                result => panic!("Unexpected result for {} bytes: {:?}", len, result),
+            }
+        }
+    }
+
+    #[test]
+    fn export() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let event = Event::interprocess(context.get_ref())?;
+        let _handle = event.ipc_handle()?;
+        // events with timing cannot be exported
+        let event = Event::new(context.get_ref());
+        assert!(event.ipc_handle().is_err());
+        Ok(())
+    }
+}
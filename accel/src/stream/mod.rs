@@ -0,0 +1,500 @@
+use crate::{contexted_call, contexted_new, device::*, error::*};
+use cuda::*;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+mod callback;
+mod ipc;
+mod pool;
+mod reactor;
+
+pub use ipc::*;
+pub use pool::*;
+pub use reactor::StreamFuture;
+
+/// Handler for non-blocking CUDA Stream
+#[derive(Debug, Contexted)]
+pub struct Stream {
+    pub(crate) stream: CUstream,
+    context: ContextRef,
+    /// Message of a panic in host callbacks, which is reported by `Stream::sync`
+    callback_panic: Arc<Mutex<Option<String>>>,
+}
+
+unsafe impl Sync for Stream {}
+unsafe impl Send for Stream {}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuStreamDestroy_v2, self.stream) } {
+            log::error!("Failed to delete CUDA stream: {:?}", e);
+        }
+    }
+}
+
+impl Stream {
+    /// Create a new non-blocking CUDA stream on the current context
+    pub fn new(context: ContextRef) -> Self {
+        Self::create(context).expect("Failed to create CUDA stream")
+    }
+
+    pub(crate) fn create(context: ContextRef) -> Result<Self> {
+        let stream = unsafe {
+            contexted_new!(
+                &context,
+                cuStreamCreate,
+                CUstream_flags::CU_STREAM_NON_BLOCKING as u32
+            )?
+        };
+        Ok(Stream::from_raw(context, stream))
+    }
+
+    fn from_raw(context: ContextRef, stream: CUstream) -> Self {
+        Stream {
+            stream,
+            context,
+            callback_panic: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Create a new non-blocking CUDA stream with a priority
+    ///
+    /// Returns error if `priority` is out of `Stream::priority_range`.
+    ///
+    /// ```
+    /// # use accel::*;
+    /// let device = Device::nth(0).unwrap();
+    /// let ctx = device.create_context();
+    /// let range = Stream::priority_range(ctx.get_ref()).unwrap();
+    /// let stream = Stream::with_priority(ctx.get_ref(), range.highest()).unwrap();
+    /// assert_eq!(stream.priority().unwrap(), range.highest());
+    /// ```
+    pub fn with_priority(context: ContextRef, priority: Priority) -> Result<Self> {
+        let priority = Self::priority_range(context)?.check(priority)?;
+        let stream = unsafe {
+            contexted_new!(
+                &context,
+                cuStreamCreateWithPriority,
+                CUstream_flags::CU_STREAM_NON_BLOCKING as u32,
+                priority.0
+            )?
+        };
+        Ok(Stream::from_raw(context, stream))
+    }
+
+    /// Range of stream priorities available on the context
+    pub fn priority_range(context: ContextRef) -> Result<PriorityRange> {
+        let mut least = 0;
+        let mut greatest = 0;
+        unsafe {
+            contexted_call!(
+                &context,
+                cuCtxGetStreamPriorityRange,
+                &mut least as *mut _,
+                &mut greatest as *mut _
+            )?;
+        }
+        Ok(PriorityRange { least, greatest })
+    }
+
+    /// Priority of this stream
+    pub fn priority(&self) -> Result<Priority> {
+        let mut priority = 0;
+        unsafe {
+            contexted_call!(
+                self,
+                cuStreamGetPriority,
+                self.stream,
+                &mut priority as *mut _
+            )?;
+        }
+        Ok(Priority(priority))
+    }
+
+    /// Check all tasks in this stream have been completed
+    pub fn query(&self) -> bool {
+        match unsafe { contexted_call!(self, cuStreamQuery, self.stream) } {
+            Ok(_) => true,
+            Err(AccelError::AsyncOperationNotReady) => false,
+            Err(e) => panic!("Unknown error is happened while cuStreamQuery: {:?}", e),
+        }
+    }
+
+    /// Check if this stream is capturing
+    pub fn is_capturing(&self) -> Result<bool> {
+        let mut status = CUstreamCaptureStatus::CU_STREAM_CAPTURE_STATUS_NONE;
+        unsafe {
+            contexted_call!(
+                self,
+                cuStreamIsCapturing,
+                self.stream,
+                &mut status as *mut _
+            )?;
+        }
+        Ok(status != CUstreamCaptureStatus::CU_STREAM_CAPTURE_STATUS_NONE)
+    }
+
+    /// Wait until all tasks in this stream have been completed
+    ///
+    /// A panic in host callbacks registered by `Stream::add_callback` since the last sync
+    /// is reported as `AccelError::HostCallbackPanicked`.
+    pub fn sync(&self) -> Result<()> {
+        unsafe { contexted_call!(self, cuStreamSynchronize, self.stream) }?;
+        self.take_callback_panic()
+    }
+
+    /// Consume and convert into a Future
+    ///
+    /// This does not block any thread while waiting tasks in the stream.
+    /// See [StreamFuture](struct.StreamFuture.html) for detail.
+    pub fn into_future(self) -> StreamFuture {
+        StreamFuture::new(self)
+    }
+
+    /// Wait event to sync another stream
+    pub fn wait_event(&mut self, event: &Event) {
+        unsafe { contexted_call!(self, cuStreamWaitEvent, self.stream, event.event, 0) }
+            .expect("Failed to register an CUDA event waiting on CUDA stream");
+    }
+}
+
+/// Priority of a stream
+///
+/// As in CUDA, a lower value means a higher priority, and `0` is the default priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Priority(pub i32);
+
+/// Range of stream priorities returned by `cuCtxGetStreamPriorityRange`
+///
+/// `least` is numerically larger than or equal to `greatest`, e.g. `least = 0, greatest = -5`.
+/// Both are zero if the device does not support stream priorities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityRange {
+    pub least: i32,
+    pub greatest: i32,
+}
+
+impl PriorityRange {
+    /// The highest priority, i.e. kernels in this stream are scheduled first
+    pub fn highest(&self) -> Priority {
+        Priority(self.greatest)
+    }
+
+    /// The lowest priority
+    pub fn lowest(&self) -> Priority {
+        Priority(self.least)
+    }
+
+    pub fn contains(&self, priority: Priority) -> bool {
+        self.greatest <= priority.0 && priority.0 <= self.least
+    }
+
+    /// Round an out-of-range priority into the range as CUDA does
+    pub fn clamp(&self, priority: Priority) -> Priority {
+        Priority(priority.0.max(self.greatest).min(self.least))
+    }
+
+    /// Reject an out-of-range priority
+    pub fn check(&self, priority: Priority) -> Result<Priority> {
+        if self.contains(priority) {
+            Ok(priority)
+        } else {
+            Err(AccelError::InvalidStreamPriority {
+                priority: priority.0,
+                least: self.least,
+                greatest: self.greatest,
+            })
+        }
+    }
+}
+
+/// Handler for CUDA Event
+///
+/// Events are created with `CU_EVENT_BLOCKING_SYNC`,
+/// i.e. `Event::sync` yields the CPU thread instead of spinning.
+#[derive(Contexted)]
+pub struct Event {
+    event: CUevent,
+    timing: bool,
+    context: ContextRef,
+}
+
+unsafe impl Sync for Event {}
+unsafe impl Send for Event {}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { contexted_call!(self, cuEventDestroy_v2, self.event) } {
+            log::error!("Failed to delete CUDA event: {:?}", e);
+        }
+    }
+}
+
+impl Event {
+    /// Create a new event which records the timestamp for `Event::elapsed_since`
+    pub fn new(context: ContextRef) -> Self {
+        Self::create(context, true)
+    }
+
+    /// Create a new event without timestamp, which is lighter for synchronization
+    pub fn without_timing(context: ContextRef) -> Self {
+        Self::create(context, false)
+    }
+
+    fn create(context: ContextRef, timing: bool) -> Self {
+        let mut flags = CUevent_flags_enum::CU_EVENT_BLOCKING_SYNC as u32;
+        if !timing {
+            flags |= CUevent_flags_enum::CU_EVENT_DISABLE_TIMING as u32;
+        }
+        Self::with_flags(context, flags).expect("Failed to create CUDA event")
+    }
+
+    fn with_flags(context: ContextRef, flags: u32) -> Result<Self> {
+        let event = unsafe { contexted_new!(&context, cuEventCreate, flags)? };
+        Ok(Event {
+            context,
+            event,
+            timing: flags & CUevent_flags_enum::CU_EVENT_DISABLE_TIMING as u32 == 0,
+        })
+    }
+
+    pub fn record(&mut self, stream: &Stream) {
+        unsafe { contexted_call!(self, cuEventRecord, self.event, stream.stream) }
+            .expect("Failed to set event record");
+    }
+
+    /// Query if the event has occur, returns true if already occurs
+    pub fn query(&self) -> bool {
+        match unsafe { contexted_call!(self, cuEventQuery, self.event) } {
+            Ok(_) => true,
+            Err(AccelError::AsyncOperationNotReady) => false,
+            Err(e) => panic!("Unknown error occurs while cuEventQuery: {:?}", e),
+        }
+    }
+
+    /// Wait until the event occurs with blocking
+    pub fn sync(&self) -> Result<()> {
+        unsafe { contexted_call!(self, cuEventSynchronize, self.event) }?;
+        Ok(())
+    }
+
+    /// Elapsed time on GPU from `start` to this event
+    ///
+    /// This blocks until both events occur.
+    /// The resolution is around 0.5 microseconds.
+    /// `AccelError::InvalidElapsedTime` is returned if `start` is recorded after this event.
+    pub fn elapsed_since(&self, start: &Event) -> Result<Duration> {
+        if !self.timing || !start.timing {
+            return Err(AccelError::EventTimingDisabled);
+        }
+        start.sync()?;
+        self.sync()?;
+        let mut ms = 0.0_f32;
+        unsafe {
+            contexted_call!(
+                self,
+                cuEventElapsedTime,
+                &mut ms as *mut _,
+                start.event,
+                self.event
+            )?;
+        }
+        duration_from_millis(ms)
+    }
+}
+
+/// `cuEventElapsedTime` returns a negative value if `start` is recorded after `end`
+fn duration_from_millis(ms: f32) -> Result<Duration> {
+    if ms.is_finite() && ms >= 0.0 {
+        Ok(Duration::from_secs_f64(f64::from(ms) * 1e-3))
+    } else {
+        Err(AccelError::InvalidElapsedTime { millis: ms })
+    }
+}
+
+/// Timer measuring GPU time of tasks in a stream
+///
+/// The start event is recorded when created, and the end event is recorded by `GpuTimer::stop`.
+/// Events are released even if the timer is dropped without stopping.
+///
+/// ```
+/// # use accel::*;
+/// let device = Device::nth(0).unwrap();
+/// let ctx = device.create_context();
+/// let stream = Stream::new(ctx.get_ref());
+/// let timer = GpuTimer::start(&stream);
+/// // ... issue tasks into `stream` ...
+/// let elapsed = timer.stop().unwrap();
+/// println!("{:?}", elapsed);
+/// ```
+pub struct GpuTimer<'stream> {
+    stream: &'stream Stream,
+    start: Event,
+    end: Event,
+}
+
+impl<'stream> GpuTimer<'stream> {
+    /// Start a timer by recording an event on `stream`
+    pub fn start(stream: &'stream Stream) -> Self {
+        let mut start = Event::new(stream.get_ref());
+        let end = Event::new(stream.get_ref());
+        start.record(stream);
+        GpuTimer { stream, start, end }
+    }
+
+    /// Record the end event, and wait until all tasks issued after start have been completed
+    pub fn stop(mut self) -> Result<Duration> {
+        self.end.record(self.stream);
+        self.end.elapsed_since(&self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let _st = Stream::new(context.get_ref());
+        Ok(())
+    }
+
+    #[test]
+    fn trivial_sync() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let mut stream = Stream::new(context.get_ref());
+        let mut event = Event::new(context.get_ref());
+        event.record(&mut stream);
+        // nothing to be waited
+        event.sync()?;
+        stream.sync()?;
+        Ok(())
+    }
+
+    #[test]
+    fn duration_from_millis() {
+        // (milliseconds, nanoseconds)
+        let table = [
+            (0.0, 0),
+            (1.0, 1_000_000),
+            (0.5, 500_000),
+            (1500.0, 1_500_000_000),
+        ];
+        for &(ms, ns) in &table {
+            assert_eq!(
+                super::duration_from_millis(ms).unwrap(),
+                Duration::from_nanos(ns)
+            );
+        }
+        for &ms in &[-1.0, -0.001, std::f32::NAN, std::f32::INFINITY] {
+            match super::duration_from_millis(ms) {
+                Err(AccelError::InvalidElapsedTime { .. }) => {}
+                result => panic!("Unexpected result for {} ms: {:?}", ms, result),
+            }
+        }
+    }
+
+    #[test]
+    fn elapsed() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        let mut start = Event::new(context.get_ref());
+        let mut end = Event::new(context.get_ref());
+        start.record(&stream);
+        end.record(&stream);
+        let _ = end.elapsed_since(&start)?;
+        Ok(())
+    }
+
+    #[test]
+    fn elapsed_without_timing() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        let mut start = Event::without_timing(context.get_ref());
+        let mut end = Event::new(context.get_ref());
+        start.record(&stream);
+        end.record(&stream);
+        assert!(matches!(
+            end.elapsed_since(&start),
+            Err(AccelError::EventTimingDisabled)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn gpu_timer() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let stream = Stream::new(context.get_ref());
+        let timer = GpuTimer::start(&stream);
+        let _ = timer.stop()?;
+        // dropped without stop
+        let _timer = GpuTimer::start(&stream);
+        Ok(())
+    }
+
+    #[test]
+    fn priority_range() {
+        let range = PriorityRange {
+            least: 0,
+            greatest: -5,
+        };
+        assert_eq!(range.highest(), Priority(-5));
+        assert_eq!(range.lowest(), Priority::default());
+        // (input, clamped, valid)
+        let table = [
+            (0, 0, true),
+            (-3, -3, true),
+            (-5, -5, true),
+            (-6, -5, false),
+            (1, 0, false),
+            (i32::MIN, -5, false),
+            (i32::MAX, 0, false),
+        ];
+        for &(input, clamped, valid) in &table {
+            assert_eq!(range.clamp(Priority(input)), Priority(clamped));
+            assert_eq!(range.contains(Priority(input)), valid);
+            match range.check(Priority(input)) {
+                Ok(p) => assert!(valid && p == Priority(input)),
+                Err(AccelError::InvalidStreamPriority {
+                    priority,
+                    least,
+                    greatest,
+                }) => assert!(!valid && priority == input && least == 0 && greatest == -5),
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn priority_range_unsupported() {
+        let range = PriorityRange {
+            least: 0,
+            greatest: 0,
+        };
+        assert_eq!(range.clamp(Priority(-1)), Priority(0));
+        assert!(range.check(Priority::default()).is_ok());
+        assert!(range.check(Priority(-1)).is_err());
+    }
+
+    #[test]
+    fn with_priority() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let range = Stream::priority_range(context.get_ref())?;
+        let high = Stream::with_priority(context.get_ref(), range.highest())?;
+        let low = Stream::with_priority(context.get_ref(), range.lowest())?;
+        assert_eq!(high.priority()?, range.highest());
+        assert_eq!(low.priority()?, range.lowest());
+        assert!(Stream::with_priority(context.get_ref(), Priority(range.greatest - 1)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+//! Per-context pool of streams reused by async operations
+//!
+//! Creating and destroying a stream for each async memcpy or kernel launch has a measurable overhead.
+//! Async operations in accel check out a stream from the pool of the context,
+//! and the stream is returned into the pool when the future completes.
+
+use super::*;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Default number of idle streams kept in a pool
+pub const DEFAULT_STREAM_POOL_CAPACITY: usize = 16;
+
+/// Statistics of a stream pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PoolStats {
+    /// Number of streams created since no idle stream exists
+    pub created: usize,
+    /// Number of checkouts served by idle streams
+    pub reused: usize,
+    /// Number of streams returned into the pool
+    pub returned: usize,
+    /// Number of streams destroyed since the pool is full
+    pub discarded: usize,
+    /// Number of idle streams in the pool currently
+    pub idle: usize,
+}
+
+/// Checkout/return logic, which is independent from the driver
+#[derive(Debug)]
+struct Pool<T> {
+    idle: Vec<T>,
+    capacity: usize,
+    stats: PoolStats,
+}
+
+impl<T> Pool<T> {
+    fn new(capacity: usize) -> Self {
+        Pool {
+            idle: Vec::new(),
+            capacity,
+            stats: PoolStats::default(),
+        }
+    }
+
+    /// Take an idle item if exists
+    fn checkout(&mut self) -> Option<T> {
+        let item = self.idle.pop()?;
+        self.stats.reused += 1;
+        Some(item)
+    }
+
+    /// Record that a new item is created since `checkout` returns `None`
+    fn created(&mut self) {
+        self.stats.created += 1;
+    }
+
+    /// Return an item, or get it back if the pool is full
+    fn checkin(&mut self, item: T) -> Option<T> {
+        if self.idle.len() < self.capacity {
+            self.idle.push(item);
+            self.stats.returned += 1;
+            None
+        } else {
+            self.stats.discarded += 1;
+            Some(item)
+        }
+    }
+
+    /// Change capacity, and get back idle items exceeding it
+    fn set_capacity(&mut self, capacity: usize) -> Vec<T> {
+        self.capacity = capacity;
+        let excess = self.idle.len().saturating_sub(capacity);
+        self.stats.discarded += excess;
+        self.idle.split_off(self.idle.len() - excess)
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            idle: self.idle.len(),
+            ..self.stats
+        }
+    }
+}
+
+/// Pool of streams shared in a context
+///
+/// ```
+/// # use accel::*;
+/// let device = Device::nth(0).unwrap();
+/// let ctx = device.create_context();
+/// let pool = StreamPool::for_context(ctx.get_ref());
+/// let stream = pool.checkout().unwrap();
+/// drop(stream); // returned into the pool
+/// let _stream = pool.checkout().unwrap();
+/// assert_eq!(pool.stats().reused, 1);
+/// ```
+#[derive(Debug, Contexted)]
+pub struct StreamPool {
+    pool: Mutex<Pool<Stream>>,
+    context: ContextRef,
+}
+
+lazy_static::lazy_static! {
+    static ref POOLS: Mutex<HashMap<usize, Arc<StreamPool>>> = Mutex::new(HashMap::new());
+}
+
+fn pools() -> MutexGuard<'static, HashMap<usize, Arc<StreamPool>>> {
+    // The map is always consistent even if another thread panics
+    POOLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl StreamPool {
+    /// Get the pool of the context, which is created with `DEFAULT_STREAM_POOL_CAPACITY` at first
+    ///
+    /// The pool is shared only while the context is owned by accel,
+    /// and it is evicted when the last `Context` of it is dropped.
+    /// A context not owned by accel, e.g. created by another library or already destroyed,
+    /// gets a new pool for each call since accel cannot know when it is destroyed.
+    pub fn for_context(context: ContextRef) -> Arc<Self> {
+        let new = || {
+            Arc::new(StreamPool {
+                pool: Mutex::new(Pool::new(DEFAULT_STREAM_POOL_CAPACITY)),
+                context,
+            })
+        };
+        // Check liveness while holding the lock of pools,
+        // or `release` may run between the check and the insertion.
+        let mut pools = pools();
+        if !crate::device::registry::is_live(context.ptr) {
+            return new();
+        }
+        pools
+            .entry(context.ptr as usize)
+            .or_insert_with(new)
+            .clone()
+    }
+
+    /// Release the pool of the context before the context is destroyed
+    ///
+    /// Idle streams are destroyed even if the pool is still shared,
+    /// and checked-out streams are destroyed when they are returned.
+    pub(crate) fn release(context: ContextRef) {
+        let pool = pools().remove(&(context.ptr as usize));
+        if let Some(pool) = pool {
+            // destroyed here out of the lock of pools
+            pool.set_capacity(0);
+        }
+    }
+
+    fn pool(&self) -> MutexGuard<'_, Pool<Stream>> {
+        self.pool
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Take an idle stream, or create a new one if no idle stream exists
+    pub fn checkout(self: &Arc<Self>) -> Result<PooledStream> {
+        let stream = self.pool().checkout();
+        let stream = match stream {
+            Some(stream) => stream,
+            None => {
+                let stream = Stream::create(self.context)?;
+                self.pool().created();
+                stream
+            }
+        };
+        Ok(PooledStream {
+            stream: Some(stream),
+            pool: self.clone(),
+        })
+    }
+
+    /// Maximum number of idle streams kept in this pool
+    pub fn capacity(&self) -> usize {
+        self.pool().capacity
+    }
+
+    /// Change the maximum number of idle streams, and destroy idle streams exceeding it
+    pub fn set_capacity(&self, capacity: usize) {
+        let excess = self.pool().set_capacity(capacity);
+        drop(excess);
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.pool().stats()
+    }
+}
+
+/// Stream checked out from `StreamPool`, which is returned into the pool when dropped
+#[derive(Debug)]
+pub struct PooledStream {
+    stream: Option<Stream>,
+    pool: Arc<StreamPool>,
+}
+
+impl Borrow<Stream> for PooledStream {
+    fn borrow(&self) -> &Stream {
+        self
+    }
+}
+
+impl Deref for PooledStream {
+    type Target = Stream;
+    fn deref(&self) -> &Stream {
+        self.stream.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledStream {
+    fn drop(&mut self) {
+        if let Some(mut stream) = self.stream.take() {
+            // A panic in callbacks of this user must not be reported to the next one
+            stream.reset_callback_panic();
+            let discarded = self.pool.pool().checkin(stream);
+            drop(discarded);
+        }
+    }
+}
+
+impl PooledStream {
+    /// Consume and convert into a Future, and the stream is returned into the pool when completed
+    pub fn into_future(self) -> StreamFuture<PooledStream> {
+        StreamFuture::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkout_checkin() {
+        let mut pool = Pool::new(2);
+        assert_eq!(pool.checkout(), None);
+        pool.created();
+        assert_eq!(pool.checkin(0), None);
+        assert_eq!(pool.checkout(), Some(0));
+        assert_eq!(pool.checkin(0), None);
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                created: 1,
+                reused: 1,
+                returned: 2,
+                discarded: 0,
+                idle: 1
+            }
+        );
+    }
+
+    #[test]
+    fn full() {
+        let mut pool = Pool::new(2);
+        for _ in 0..3 {
+            assert_eq!(pool.checkout(), None);
+            pool.created();
+        }
+        // three items are checked out, but only two can be kept
+        assert_eq!(pool.checkin(0), None);
+        assert_eq!(pool.checkin(1), None);
+        assert_eq!(pool.checkin(2), Some(2));
+        let stats = pool.stats();
+        assert_eq!(stats.created, 3);
+        assert_eq!(stats.returned, 2);
+        assert_eq!(stats.discarded, 1);
+        assert_eq!(stats.idle, 2);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut pool = Pool::new(0);
+        assert_eq!(pool.checkout(), None);
+        assert_eq!(pool.checkin(0), Some(0));
+        assert_eq!(pool.stats().idle, 0);
+    }
+
+    #[test]
+    fn shrink() {
+        let mut pool = Pool::new(4);
+        for i in 0..4 {
+            assert_eq!(pool.checkin(i), None);
+        }
+        let mut excess = pool.set_capacity(1);
+        excess.sort_unstable();
+        assert_eq!(excess, vec![1, 2, 3]);
+        assert_eq!(pool.stats().idle, 1);
+        assert_eq!(pool.stats().discarded, 3);
+        assert_eq!(pool.checkin(4), Some(4));
+        // grow again
+        assert!(pool.set_capacity(2).is_empty());
+        assert_eq!(pool.checkin(4), None);
+    }
+
+    #[test]
+    fn reuse() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let pool = StreamPool::for_context(context.get_ref());
+        assert!(Arc::ptr_eq(
+            &pool,
+            &StreamPool::for_context(context.get_ref())
+        ));
+        for _ in 0..10 {
+            let stream = pool.checkout()?;
+            futures::executor::block_on(stream.into_future())?;
+        }
+        let stats = pool.stats();
+        assert_eq!(stats.created, 1);
+        assert_eq!(stats.reused, 9);
+        assert_eq!(stats.idle, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn reset_callback_panic() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let pool = StreamPool::for_context(context.get_ref());
+        let stream = pool.checkout()?;
+        stream.add_callback(|| panic!("in callback"))?;
+        while !stream.query() {}
+        drop(stream);
+        // the same stream is reused
+        let stream = pool.checkout()?;
+        assert_eq!(pool.stats().reused, 1);
+        stream.sync()?;
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_context() {
+        // never returned by the driver
+        let context = ContextRef {
+            ptr: usize::MAX as CUcontext,
+        };
+        let pool = StreamPool::for_context(context);
+        assert!(!Arc::ptr_eq(&pool, &StreamPool::for_context(context)));
+        assert!(!pools().contains_key(&(context.ptr as usize)));
+    }
+
+    #[test]
+    fn evict() -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let ptr = context.get_ref().ptr;
+        let pool = StreamPool::for_context(context.get_ref());
+        let stream = pool.checkout()?;
+        stream.sync()?;
+        assert!(pools().contains_key(&(ptr as usize)));
+        drop(stream);
+        assert_eq!(pool.stats().idle, 1);
+        drop(context);
+        assert!(!pools().contains_key(&(ptr as usize)));
+        // idle streams are destroyed with the context even if the pool is still shared
+        assert_eq!(pool.stats().idle, 0);
+        Ok(())
+    }
+}
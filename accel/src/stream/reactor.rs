@@ -0,0 +1,338 @@
+//! Background reactor waking futures of streams
+//!
+//! `StreamFuture` registers a slot in the reactor, and enqueues a host callback into its stream.
+//! The callback only sends the slot id to the reactor thread,
+//! and the reactor thread wakes the task waiting on the slot.
+//! Thus no thread is blocked while GPU is working, and wakers never run on the driver thread.
+
+use super::*;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Sender},
+        Mutex, MutexGuard,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// State of a registered future
+#[derive(Debug)]
+enum Slot {
+    /// Not completed yet. The waker is stored after the first poll.
+    Pending(Option<Waker>),
+    /// Completed, but the future has not been polled since then
+    Completed,
+}
+
+/// Bookkeeping of wakers, which is independent from the driver
+#[derive(Debug, Default)]
+struct Registry {
+    next_id: usize,
+    slots: HashMap<usize, Slot>,
+}
+
+impl Registry {
+    /// Register a new pending slot
+    fn register(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.slots.insert(id, Slot::Pending(None));
+        id
+    }
+
+    /// Check completion, or store the waker to be woken on completion
+    ///
+    /// The slot is removed when it is ready.
+    fn poll(&mut self, id: usize, waker: &Waker) -> Poll<()> {
+        match self.slots.get_mut(&id) {
+            Some(Slot::Pending(stored)) => {
+                match stored {
+                    Some(w) if w.will_wake(waker) => {}
+                    _ => *stored = Some(waker.clone()),
+                }
+                Poll::Pending
+            }
+            Some(Slot::Completed) => {
+                self.slots.remove(&id);
+                Poll::Ready(())
+            }
+            None => panic!("Unknown slot of stream reactor: {}", id),
+        }
+    }
+
+    /// Mark the slot as completed, and returns the waker to be woken
+    ///
+    /// Completion of a cancelled slot is ignored.
+    fn complete(&mut self, id: usize) -> Option<Waker> {
+        match self.slots.get_mut(&id) {
+            Some(slot) => match std::mem::replace(slot, Slot::Completed) {
+                Slot::Pending(waker) => waker,
+                Slot::Completed => None,
+            },
+            None => None,
+        }
+    }
+
+    /// Remove the slot whose future is dropped
+    fn cancel(&mut self, id: usize) {
+        self.slots.remove(&id);
+    }
+}
+
+struct Reactor {
+    registry: Mutex<Registry>,
+    sender: Mutex<Sender<usize>>,
+}
+
+lazy_static::lazy_static! {
+    static ref REACTOR: Reactor = Reactor::start();
+}
+
+impl Reactor {
+    fn start() -> Self {
+        let (sender, receiver) = channel::<usize>();
+        thread::Builder::new()
+            .name("accel-reactor".into())
+            .spawn(move || {
+                for id in receiver {
+                    // wake out of the lock since the waker may poll the future immediately
+                    let waker = REACTOR.registry().complete(id);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            })
+            .expect("Failed to start stream reactor thread");
+        Reactor {
+            registry: Mutex::new(Registry::default()),
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn registry(&self) -> MutexGuard<'_, Registry> {
+        // The registry is always consistent even if another thread panics
+        self.registry
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Notify completion of the slot to the reactor thread
+    fn notify(&self, id: usize) {
+        let sender = self
+            .sender
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if sender.send(id).is_err() {
+            log::error!("Stream reactor thread has been stopped");
+        }
+    }
+}
+
+/// Future completed when all tasks in the stream have been completed
+///
+/// This is created by `Stream::into_future` or `PooledStream::into_future`,
+/// and the stream is dropped, i.e. returned into the pool, when completed.
+/// Dropping this before completion blocks until the stream is synchronized,
+/// since tasks in the stream may use memories borrowed by the caller.
+pub struct StreamFuture<S: Borrow<Stream> = Stream> {
+    stream: Option<S>,
+    id: Option<usize>,
+}
+
+impl<S: Borrow<Stream>> StreamFuture<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        StreamFuture {
+            stream: Some(stream),
+            id: None,
+        }
+    }
+}
+
+impl<S: Borrow<Stream> + Unpin> Future for StreamFuture<S> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        let stream = this
+            .stream
+            .as_ref()
+            .expect("StreamFuture is polled after completion")
+            .borrow();
+        let id = match this.id {
+            Some(id) => id,
+            None => {
+                let id = REACTOR.registry().register();
+                if let Err(e) = stream.add_callback(move || REACTOR.notify(id)) {
+                    REACTOR.registry().cancel(id);
+                    this.stream = None;
+                    return Poll::Ready(Err(e));
+                }
+                this.id = Some(id);
+                id
+            }
+        };
+        match REACTOR.registry().poll(id, cx.waker()) {
+            Poll::Ready(()) => {
+                this.id = None;
+                // tasks have been completed, and this only returns errors occurred in them
+                let result = stream.sync();
+                this.stream = None;
+                Poll::Ready(result)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: Borrow<Stream>> Drop for StreamFuture<S> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            REACTOR.registry().cancel(id);
+        }
+        if let Some(stream) = self.stream.take() {
+            if let Err(e) = stream.borrow().sync() {
+                log::error!("Failed to sync stream of dropped future: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::{waker, ArcWake};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::Receiver,
+        Arc,
+    };
+
+    #[derive(Default)]
+    struct CountWaker(AtomicUsize);
+
+    impl ArcWake for CountWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn count_waker() -> (Arc<CountWaker>, Waker) {
+        let count = Arc::new(CountWaker::default());
+        (count.clone(), waker(count))
+    }
+
+    #[test]
+    fn complete_after_poll() {
+        let mut registry = Registry::default();
+        let (count, waker) = count_waker();
+        let id = registry.register();
+        assert_eq!(registry.poll(id, &waker), Poll::Pending);
+        assert_eq!(registry.poll(id, &waker), Poll::Pending);
+        registry.complete(id).unwrap().wake();
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.poll(id, &waker), Poll::Ready(()));
+        assert!(registry.slots.is_empty());
+    }
+
+    #[test]
+    fn complete_before_poll() {
+        let mut registry = Registry::default();
+        let (_count, waker) = count_waker();
+        let id = registry.register();
+        assert!(registry.complete(id).is_none());
+        assert_eq!(registry.poll(id, &waker), Poll::Ready(()));
+        assert!(registry.slots.is_empty());
+    }
+
+    #[test]
+    fn replace_waker() {
+        let mut registry = Registry::default();
+        let (count1, waker1) = count_waker();
+        let (count2, waker2) = count_waker();
+        let id = registry.register();
+        assert_eq!(registry.poll(id, &waker1), Poll::Pending);
+        // the task is moved to another executor
+        assert_eq!(registry.poll(id, &waker2), Poll::Pending);
+        registry.complete(id).unwrap().wake();
+        assert_eq!(count1.0.load(Ordering::SeqCst), 0);
+        assert_eq!(count2.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cancel() {
+        let mut registry = Registry::default();
+        let (_count, waker) = count_waker();
+        let id1 = registry.register();
+        let id2 = registry.register();
+        assert_ne!(id1, id2);
+        assert_eq!(registry.poll(id1, &waker), Poll::Pending);
+        registry.cancel(id1);
+        // callback of the cancelled future comes later
+        assert!(registry.complete(id1).is_none());
+        assert_eq!(registry.slots.len(), 1);
+        registry.complete(id2);
+        assert_eq!(registry.poll(id2, &waker), Poll::Ready(()));
+        assert!(registry.slots.is_empty());
+    }
+
+    struct ChannelWaker(Mutex<Sender<()>>);
+
+    impl ArcWake for ChannelWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.lock().unwrap().send(()).unwrap();
+        }
+    }
+
+    fn channel_waker() -> (Receiver<()>, Waker) {
+        let (tx, rx) = channel();
+        (rx, waker(Arc::new(ChannelWaker(Mutex::new(tx)))))
+    }
+
+    #[test]
+    fn reactor_thread() {
+        let (rx, waker) = channel_waker();
+        let ids: Vec<usize> = (0..100).map(|_| REACTOR.registry().register()).collect();
+        for &id in &ids {
+            assert_eq!(REACTOR.registry().poll(id, &waker), Poll::Pending);
+        }
+        for &id in &ids {
+            REACTOR.notify(id);
+        }
+        for _ in &ids {
+            rx.recv().unwrap();
+        }
+        for &id in &ids {
+            assert_eq!(REACTOR.registry().poll(id, &waker), Poll::Ready(()));
+        }
+    }
+
+    async fn wait_streams(n: usize) -> Result<()> {
+        let device = Device::nth(0)?;
+        let context = device.create_context();
+        let futures: Vec<_> = (0..n)
+            .map(|_| Stream::new(context.get_ref()).into_future())
+            .collect();
+        for result in futures::future::join_all(futures).await {
+            result?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stream_future() -> Result<()> {
+        futures::executor::block_on(wait_streams(300))
+    }
+
+    #[tokio::test]
+    async fn stream_future_tokio() -> Result<()> {
+        wait_streams(300).await
+    }
+
+    #[async_std::test]
+    async fn stream_future_async_std() -> Result<()> {
+        wait_streams(300).await
+    }
+}
@@ -8,8 +8,11 @@ unsafe fn add(a: *const u32, b: *const u32, c: *mut u32, n: usize) {
     }
 }
 
-#[tokio::main]
-async fn main() -> error::Result<()> {
+fn main() -> error::Result<()> {
+    futures::executor::block_on(run())
+}
+
+async fn run() -> error::Result<()> {
     let device = Device::nth(0)?;
     let ctx = device.create_context();
     let n = 16;
@@ -0,0 +1,139 @@
+use accel::*;
+
+#[kernel]
+unsafe fn set1(a: *mut i32, n: usize) {
+    let i = accel_core::index();
+    if i < n as isize {
+        *a.offset(i) = 1;
+    }
+}
+
+/// Reverse `a` of length `n` in a block through `extern __shared__ int smem[]`
+const PTX_REVERSE: &str = r#"
+.version 6.0
+.target sm_30
+.address_size 64
+
+.extern .shared .align 4 .b8 smem[];
+
+.visible .entry reverse(
+    .param .u64 reverse_param_0,
+    .param .u64 reverse_param_1
+)
+{
+    .reg .b32 %r<3>;
+    .reg .b64 %rd<12>;
+
+    ld.param.u64 %rd1, [reverse_param_0];
+    ld.param.u64 %rd2, [reverse_param_1];
+    cvta.to.global.u64 %rd3, %rd1;
+    mov.u32 %r1, %tid.x;
+    cvt.u64.u32 %rd4, %r1;
+    shl.b64 %rd5, %rd4, 2;
+    add.s64 %rd6, %rd3, %rd5;
+    mov.u64 %rd7, smem;
+    add.s64 %rd8, %rd7, %rd5;
+    ld.global.u32 %r1, [%rd6];
+    st.shared.u32 [%rd8], %r1;
+    bar.sync 0;
+    sub.s64 %rd9, %rd2, %rd4;
+    add.s64 %rd9, %rd9, -1;
+    shl.b64 %rd10, %rd9, 2;
+    add.s64 %rd11, %rd7, %rd10;
+    ld.shared.u32 %r2, [%rd11];
+    st.global.u32 [%rd6], %r2;
+    ret;
+}
+"#;
+
+struct Reverse(Module);
+
+impl<'arg> execution::Launchable2<'arg> for Reverse {
+    type Target1 = *mut i32;
+    type Target2 = usize;
+    fn get_kernel(&self) -> error::Result<Kernel> {
+        self.0.get_kernel("reverse")
+    }
+}
+
+#[test]
+fn dynamic_shared_memory() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = Reverse(Module::from_str(&ctx, PTX_REVERSE)?);
+    let n = 64;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    for (i, a) in a.iter_mut().enumerate() {
+        *a = i as i32;
+    }
+    let config = LaunchConfig::new(1, n).shared_mem((n * std::mem::size_of::<i32>()) as u32);
+    unsafe { module.launch_with(config, (&mut a, n))? };
+    let expected: Vec<i32> = (0..n as i32).rev().collect();
+    assert_eq!(a.as_slice(), expected.as_slice());
+    Ok(())
+}
+
+#[test]
+fn free_fn_on_stream() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = set1::Module::new(&ctx)?;
+    let stream = Stream::new(ctx.get_ref());
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    for _ in 0..3 {
+        unsafe { set1::launch_with(&module, LaunchConfig::new(1, n).on(&stream), (&mut a, n))? };
+    }
+    stream.sync()?;
+    assert_eq!(a.as_slice(), vec![1_i32; n].as_slice());
+    Ok(())
+}
+
+#[test]
+fn too_large_shared_memory() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = set1::Module::new(&ctx)?;
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    let config = LaunchConfig::new(1, n).shared_mem(1 << 30);
+    assert!(unsafe { set1::launch_with(&module, config, (&mut a, n)) }.is_err());
+    // more than 48KB requires opt-in of the kernel even if the device supports it
+    let config = LaunchConfig::new(1, n).shared_mem(48 * 1024 + 4);
+    assert!(config.validate_for(&module.get_kernel()?).is_err());
+    match unsafe { set1::launch_with(&module, config, (&mut a, n)) } {
+        Err(error::AccelError::InvalidLaunchConfig { .. }) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    Ok(())
+}
+
+#[test]
+fn invalid_config_async() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = set1::Module::new(&ctx)?;
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    // 4096 threads per block
+    let future = module.launch_async(1, (64, 64, 1), (&mut a, n));
+    match futures::executor::block_on(future) {
+        Err(error::AccelError::InvalidLaunchConfig { .. }) => {}
+        result => panic!("Unexpected result: {:?}", result),
+    }
+    Ok(())
+}
+
+#[test]
+fn config_on_stream() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = set1::Module::new(&ctx)?;
+    let stream = Stream::new(ctx.get_ref());
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    unsafe { module.launch_with(LaunchConfig::new(1, n).on(&stream), (&mut a, n))? };
+    stream.sync()?;
+    assert_eq!(a.as_slice(), vec![1_i32; n].as_slice());
+    Ok(())
+}
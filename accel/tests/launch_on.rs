@@ -0,0 +1,45 @@
+use accel::*;
+
+#[kernel]
+unsafe fn add(a: *mut i32, b: i32, n: usize) {
+    let i = accel_core::index();
+    if i < n as isize {
+        *a.offset(i) += b;
+    }
+}
+
+#[test]
+fn chain_in_stream() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = add::Module::new(&ctx)?;
+    let stream = Stream::new(ctx.get_ref());
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+    for _ in 0..10 {
+        unsafe { module.launch_on(&stream, 1, n, (&mut a, 1, n))? };
+    }
+    stream.sync()?;
+    assert_eq!(a.as_slice(), vec![10_i32; n].as_slice());
+    Ok(())
+}
+
+#[test]
+fn wait_event_across_streams() -> error::Result<()> {
+    let device = Device::nth(0)?;
+    let ctx = device.create_context();
+    let module = add::Module::new(&ctx)?;
+    let stream1 = Stream::new(ctx.get_ref());
+    let mut stream2 = Stream::new(ctx.get_ref());
+    let n = 12;
+    let mut a = DeviceMemory::<i32>::zeros(&ctx, n);
+
+    unsafe { module.launch_on(&stream1, 1, n, (&mut a, 1, n))? };
+    let mut event = Event::without_timing(ctx.get_ref());
+    event.record(&stream1);
+    stream2.wait_event(&event);
+    unsafe { module.launch_on(&stream2, 1, n, (&mut a, 2, n))? };
+    stream2.sync()?;
+    assert_eq!(a.as_slice(), vec![3_i32; n].as_slice());
+    Ok(())
+}